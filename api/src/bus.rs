@@ -0,0 +1,198 @@
+use futures_util::StreamExt;
+use protocol::from_api::{IncomingMessage, MessageFromApi};
+use protocol::to_api::MessageToApi;
+use protocol::transport::{RedisConfig, MESSAGES_QUEUE};
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+// every reply channel of this process starts with "api:{instance}:"
+const REPLY_PREFIX: &str = "api";
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<MessageToApi>>>>;
+
+#[derive(Debug)]
+pub enum BusError {
+    Redis(redis::RedisError),
+    Encode(serde_json::Error),
+    Timeout,
+    Closed,
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Redis(e) => write!(f, "redis: {}", e),
+            BusError::Encode(e) => write!(f, "failed to encode message: {}", e),
+            BusError::Timeout => write!(f, "engine did not reply in time"),
+            BusError::Closed => write!(f, "reply listener stopped"),
+        }
+    }
+}
+
+impl From<redis::RedisError> for BusError {
+    fn from(e: redis::RedisError) -> Self {
+        BusError::Redis(e)
+    }
+}
+
+impl From<serde_json::Error> for BusError {
+    fn from(e: serde_json::Error) -> Self {
+        BusError::Encode(e)
+    }
+}
+
+/// Request/reply over Redis: commands are pushed onto the engine's "messages" list with a
+/// fresh client id, and the engine publishes its answer on a channel named after that id.
+/// One pattern subscription per process collects the answers for all in-flight requests.
+pub struct ApiBus {
+    client: redis::Client,
+    conn: tokio::sync::Mutex<Option<MultiplexedConnection>>,
+    pending: Pending,
+    instance: String,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+impl ApiBus {
+    /// Connects and subscribes before returning, so no reply can arrive unheard.
+    pub async fn connect(config: &RedisConfig, timeout: Duration) -> Result<Self, BusError> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let instance = format!("{:x}{:x}", std::process::id(), nanos);
+        let pattern = format!("{}:{}:*", REPLY_PREFIX, instance);
+
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.psubscribe(&pattern).await?;
+        let conn = client.get_multiplexed_tokio_connection().await?;
+
+        let pending: Pending = Arc::default();
+        actix_web::rt::spawn(listen(client.clone(), pubsub, pattern, pending.clone()));
+
+        Ok(ApiBus {
+            client,
+            conn: tokio::sync::Mutex::new(Some(conn)),
+            pending,
+            instance,
+            next_id: AtomicU64::new(0),
+            timeout,
+        })
+    }
+
+    /// Sends `message` to the engine and waits for its reply.
+    pub async fn request(&self, message: MessageFromApi) -> Result<MessageToApi, BusError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client_id = format!("{}:{}:{}", REPLY_PREFIX, self.instance, id);
+        let payload = serde_json::to_string(&IncomingMessage {
+            client_id: client_id.clone(),
+            message,
+        })?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(client_id.clone(), sender);
+
+        let reply = match self.push(payload).await {
+            Ok(()) => match tokio::time::timeout(self.timeout, receiver).await {
+                Ok(Ok(reply)) => Ok(reply),
+                Ok(Err(_)) => Err(BusError::Closed),
+                Err(_) => Err(BusError::Timeout),
+            },
+            Err(e) => Err(e),
+        };
+        self.pending.lock().unwrap().remove(&client_id);
+        reply
+    }
+
+    // a failed connection is dropped and reopened by the next request
+    async fn push(&self, payload: String) -> Result<(), BusError> {
+        let mut slot = self.conn.lock().await;
+        let mut conn = match slot.as_ref() {
+            Some(conn) => conn.clone(),
+            None => slot
+                .insert(self.client.get_multiplexed_tokio_connection().await?)
+                .clone(),
+        };
+        drop(slot);
+
+        if let Err(e) = conn.lpush::<_, _, ()>(MESSAGES_QUEUE, payload).await {
+            *self.conn.lock().await = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+// Hands each reply to the request waiting on its channel, resubscribing with backoff
+// whenever the subscription drops. Replies published while it is down are lost and those
+// requests time out.
+async fn listen(
+    client: redis::Client,
+    pubsub: redis::aio::PubSub,
+    pattern: String,
+    pending: Pending,
+) {
+    let mut pubsub = Some(pubsub);
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let mut subscription = match pubsub.take() {
+            Some(subscription) => subscription,
+            None => match subscribe(&client, &pattern).await {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to subscribe to replies, retrying in {:?}: {}",
+                        backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            },
+        };
+        backoff = INITIAL_BACKOFF;
+
+        let mut messages = subscription.on_message();
+        while let Some(msg) = messages.next().await {
+            let channel = msg.get_channel_name().to_string();
+            let reply = msg
+                .get_payload::<String>()
+                .map_err(|e| e.to_string())
+                .and_then(|payload| {
+                    serde_json::from_str::<MessageToApi>(&payload).map_err(|e| e.to_string())
+                });
+            match reply {
+                Ok(reply) => {
+                    if let Some(sender) = pending.lock().unwrap().remove(&channel) {
+                        // the request may have timed out in the meantime
+                        let _ = sender.send(reply);
+                    }
+                }
+                Err(e) => eprintln!("Dropping unreadable reply on {}: {}", channel, e),
+            }
+        }
+        eprintln!("Reply subscription closed, reconnecting");
+    }
+}
+
+async fn subscribe(
+    client: &redis::Client,
+    pattern: &str,
+) -> redis::RedisResult<redis::aio::PubSub> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.psubscribe(pattern).await?;
+    Ok(pubsub)
+}
@@ -1,3 +1,35 @@
-fn main() {
-    println!("Hello, world!");
+use actix_web::{web, App, HttpServer};
+use protocol::transport::RedisConfig;
+use std::env;
+use std::time::Duration;
+
+mod bus;
+mod routes;
+
+use bus::{ApiBus, DEFAULT_TIMEOUT};
+
+const DEFAULT_API_ADDR: &str = "127.0.0.1:3000";
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let addr = env::var("API_ADDR").unwrap_or_else(|_| DEFAULT_API_ADDR.to_string());
+    // how long a request waits for the engine before answering 504
+    let timeout = env::var("API_TIMEOUT_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+
+    let bus = ApiBus::connect(&RedisConfig::from_env(), timeout)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let bus = web::Data::new(bus);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(bus.clone())
+            .configure(routes::configure)
+    })
+    .bind(addr)?
+    .run()
+    .await
 }
@@ -0,0 +1,228 @@
+use crate::bus::{ApiBus, BusError};
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use protocol::from_api::{
+    CancelOrder, CreateOrder, GetBalances, GetDepth, GetKlines, GetOpenOrders, GetTicker,
+    MessageFromApi, OnRamp,
+};
+use protocol::kline::KlineInterval;
+use protocol::order::OrderInputSchema;
+use protocol::to_api::{ErrorKind, ErrorMessage, MessageToApi};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct DepthQuery {
+    pub symbol: String,
+}
+
+#[derive(Deserialize)]
+pub struct TickerQuery {
+    pub symbol: String,
+}
+
+// start and end are ms since the epoch and bound the candles' open times, both inclusive.
+// Only the newest 1000 candles per interval are kept, e.g. about 16 hours of 1m candles,
+// so a range reaching further back comes back cut short.
+#[derive(Deserialize)]
+pub struct KlinesQuery {
+    pub symbol: String,
+    pub interval: KlineInterval,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenOrdersQuery {
+    pub user_id: String,
+    pub symbol: String,
+}
+
+#[derive(Deserialize)]
+pub struct BalancesQuery {
+    pub user_id: String,
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1")
+            .service(post_order)
+            .service(delete_order)
+            .service(get_depth)
+            .service(get_ticker)
+            .service(get_klines)
+            .service(get_open_orders)
+            .service(get_balances)
+            .service(post_onramp),
+    );
+}
+
+#[post("/order")]
+async fn post_order(req: web::Json<OrderInputSchema>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = CreateOrder::from(req.into_inner());
+    respond(bus.request(MessageFromApi::CreateOrder { data }).await)
+}
+
+#[delete("/order")]
+async fn delete_order(req: web::Json<CancelOrder>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = req.into_inner();
+    respond(bus.request(MessageFromApi::CancelOrder { data }).await)
+}
+
+#[get("/depth")]
+async fn get_depth(query: web::Query<DepthQuery>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = GetDepth {
+        market: query.into_inner().symbol,
+    };
+    respond(bus.request(MessageFromApi::GetDepth { data }).await)
+}
+
+#[get("/ticker")]
+async fn get_ticker(query: web::Query<TickerQuery>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = GetTicker {
+        market: query.into_inner().symbol,
+    };
+    respond(bus.request(MessageFromApi::GetTicker { data }).await)
+}
+
+#[get("/klines")]
+async fn get_klines(query: web::Query<KlinesQuery>, bus: web::Data<ApiBus>) -> impl Responder {
+    let query = query.into_inner();
+    let data = GetKlines {
+        market: query.symbol,
+        interval: query.interval,
+        start_time: query.start,
+        end_time: query.end,
+    };
+    respond(bus.request(MessageFromApi::GetKlines { data }).await)
+}
+
+#[get("/orders/open")]
+async fn get_open_orders(
+    query: web::Query<OpenOrdersQuery>,
+    bus: web::Data<ApiBus>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let data = GetOpenOrders {
+        user_id: query.user_id,
+        market: query.symbol,
+    };
+    respond(bus.request(MessageFromApi::GetOpenOrders { data }).await)
+}
+
+#[get("/balances")]
+async fn get_balances(query: web::Query<BalancesQuery>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = GetBalances {
+        user_id: query.into_inner().user_id,
+    };
+    respond(bus.request(MessageFromApi::GetBalances { data }).await)
+}
+
+#[post("/onramp")]
+async fn post_onramp(req: web::Json<OnRamp>, bus: web::Data<ApiBus>) -> impl Responder {
+    let data = req.into_inner();
+    respond(bus.request(MessageFromApi::OnRamp { data }).await)
+}
+
+// replies go out as their bare payload, engine errors with the status their kind calls for
+fn respond(reply: Result<MessageToApi, BusError>) -> HttpResponse {
+    match reply {
+        Ok(MessageToApi::Depth { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::OrderPlaced { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::OrderCancelled { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::OpenOrders { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::Balances { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::Ticker { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::Klines { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::MarketUpdated { payload }) => HttpResponse::Ok().json(payload),
+        Ok(MessageToApi::OrderRejected { payload }) => HttpResponse::BadRequest().json(payload),
+        Ok(MessageToApi::Error { payload }) => {
+            HttpResponse::build(status(payload.kind)).json(payload)
+        }
+        Err(BusError::Timeout) => HttpResponse::GatewayTimeout().json(error(BusError::Timeout)),
+        Err(e) => {
+            eprintln!("Failed to reach the engine: {}", e);
+            HttpResponse::ServiceUnavailable().json(error(e))
+        }
+    }
+}
+
+fn status(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::Invalid => StatusCode::BAD_REQUEST,
+        ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        ErrorKind::Forbidden => StatusCode::FORBIDDEN,
+        ErrorKind::Conflict => StatusCode::CONFLICT,
+        ErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn error(e: BusError) -> ErrorMessage {
+    ErrorMessage {
+        kind: ErrorKind::Unavailable,
+        message: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::markets::RejectReason;
+    use protocol::to_api::{Balances, OrderRejected};
+    use std::collections::HashMap;
+
+    fn engine_error(kind: ErrorKind) -> Result<MessageToApi, BusError> {
+        Ok(MessageToApi::Error {
+            payload: ErrorMessage {
+                kind,
+                message: String::new(),
+            },
+        })
+    }
+
+    #[test]
+    fn replies_are_ok_and_rejections_bad_requests() {
+        let balances = Ok(MessageToApi::Balances {
+            payload: Balances {
+                user_id: "alice".to_string(),
+                balances: HashMap::new(),
+            },
+        });
+        assert_eq!(respond(balances).status(), StatusCode::OK);
+
+        let rejected = Ok(MessageToApi::OrderRejected {
+            payload: OrderRejected {
+                reason: RejectReason::InsufficientFunds,
+                message: RejectReason::InsufficientFunds.to_string(),
+            },
+        });
+        assert_eq!(respond(rejected).status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn engine_errors_map_to_the_status_of_their_kind() {
+        let statuses = [
+            (ErrorKind::Invalid, StatusCode::BAD_REQUEST),
+            (ErrorKind::NotFound, StatusCode::NOT_FOUND),
+            (ErrorKind::Forbidden, StatusCode::FORBIDDEN),
+            (ErrorKind::Conflict, StatusCode::CONFLICT),
+            (ErrorKind::Unavailable, StatusCode::SERVICE_UNAVAILABLE),
+            (ErrorKind::Internal, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (kind, status) in statuses {
+            assert_eq!(respond(engine_error(kind)).status(), status, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn bus_failures_are_gateway_errors() {
+        assert_eq!(
+            respond(Err(BusError::Timeout)).status(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            respond(Err(BusError::Closed)).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use protocol::db::{DbEntry, DbMessage};
+use protocol::from_api::{IncomingMessage, MessageFromApi};
+use protocol::to_api::MessageToApi;
+use protocol::to_ws::WsMessage;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Everything the engine exchanges with the outside world. Implementations are shared
+/// between the engine and its command loop, so waiting in `receive_command` must not block
+/// the other methods.
+pub trait MessageBus: Send + Sync {
+    /// Queues an update for the db processor.
+    fn push_to_db(&self, entry: &DbEntry) -> Result<(), BusError>;
+
+    /// Publishes to a websocket stream such as `depth@TATA_INR`.
+    fn publish_to_ws(&self, channel: &str, msg: &WsMessage) -> Result<(), BusError>;
+
+    /// Replies to the API request identified by `client_id`.
+    fn send_to_api(&self, client_id: &str, msg: &MessageToApi) -> Result<(), BusError>;
+
+    /// Waits up to `timeout` for the next raw `{clientId, message}` payload.
+    fn receive_command(&self, timeout: Duration) -> Result<Option<String>, BusError>;
+
+    /// Fails once an update for the db processor has been lost, after which the engine stops
+    /// taking commands rather than let the db fall further behind.
+    fn status(&self) -> Result<(), BusError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum BusError {
+    Redis(redis::RedisError),
+    Encode(serde_json::Error),
+    PublisherStopped,
+    QueueFull,
+    DbUpdateLost(String),
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Redis(e) => write!(f, "redis: {}", e),
+            BusError::Encode(e) => write!(f, "failed to encode message: {}", e),
+            BusError::PublisherStopped => write!(f, "redis publisher thread has stopped"),
+            BusError::QueueFull => write!(f, "outgoing queue is full"),
+            BusError::DbUpdateLost(e) => write!(f, "an update for the db was lost: {}", e),
+        }
+    }
+}
+
+impl From<redis::RedisError> for BusError {
+    fn from(e: redis::RedisError) -> Self {
+        BusError::Redis(e)
+    }
+}
+
+impl From<serde_json::Error> for BusError {
+    fn from(e: serde_json::Error) -> Self {
+        BusError::Encode(e)
+    }
+}
+
+/// Bus backed by in-process queues, for running the engine without Redis. Commands are
+/// queued with `push_command`; everything the engine sends is recorded until taken.
+#[derive(Default)]
+pub struct InMemoryBus {
+    commands: Mutex<VecDeque<String>>,
+    available: Condvar,
+    db: Mutex<Vec<DbEntry>>,
+    ws: Mutex<Vec<(String, WsMessage)>>,
+    api: Mutex<Vec<(String, MessageToApi)>>,
+}
+
+impl InMemoryBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_command(&self, client_id: &str, message: MessageFromApi) {
+        let incoming = IncomingMessage {
+            client_id: client_id.to_string(),
+            message,
+        };
+        self.push_raw(serde_json::to_string(&incoming).unwrap());
+    }
+
+    // lets tests feed payloads the API would never produce
+    pub fn push_raw(&self, payload: String) {
+        self.commands.lock().unwrap().push_back(payload);
+        self.available.notify_one();
+    }
+
+    pub fn take_db_messages(&self) -> Vec<DbMessage> {
+        self.take_db_entries()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    pub fn take_db_entries(&self) -> Vec<DbEntry> {
+        std::mem::take(&mut *self.db.lock().unwrap())
+    }
+
+    pub fn take_ws_messages(&self) -> Vec<(String, WsMessage)> {
+        std::mem::take(&mut *self.ws.lock().unwrap())
+    }
+
+    pub fn take_api_messages(&self) -> Vec<(String, MessageToApi)> {
+        std::mem::take(&mut *self.api.lock().unwrap())
+    }
+}
+
+impl MessageBus for InMemoryBus {
+    fn push_to_db(&self, entry: &DbEntry) -> Result<(), BusError> {
+        self.db.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+
+    fn publish_to_ws(&self, channel: &str, msg: &WsMessage) -> Result<(), BusError> {
+        self.ws
+            .lock()
+            .unwrap()
+            .push((channel.to_string(), msg.clone()));
+        Ok(())
+    }
+
+    fn send_to_api(&self, client_id: &str, msg: &MessageToApi) -> Result<(), BusError> {
+        self.api
+            .lock()
+            .unwrap()
+            .push((client_id.to_string(), msg.clone()));
+        Ok(())
+    }
+
+    fn receive_command(&self, timeout: Duration) -> Result<Option<String>, BusError> {
+        let commands = self.commands.lock().unwrap();
+        let (mut commands, _) = self
+            .available
+            .wait_timeout_while(commands, timeout, |commands| commands.is_empty())
+            .unwrap();
+        Ok(commands.pop_front())
+    }
+}
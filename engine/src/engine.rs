@@ -1,40 +1,261 @@
+use crate::bus::MessageBus;
+use crate::journal::{Journal, DEFAULT_JOURNAL_PATH};
+use crate::klines::Klines;
 use crate::orderbook::*;
-use crate::redis_manager;
-use crate::redis_manager::TradeAdded;
-use crate::typs::from_api::*;
-
-use crate::typs::to_ws::TradeData;
-use crate::Kind;
-use crate::Market;
+use crate::redis_manager::RedisManager;
+use crate::snapshot::{
+    SnapshotConfig, SnapshotData, SnapshotError, SnapshotStore, SnapshotWriter, LEGACY_JSON_VERSION,
+};
+use crate::ticker::Ticker;
+use crate::utils::{now_millis, Sequence};
 use crate::OrderBook;
+use protocol::db::{DbEntry, DbMessage, OrderUpdate, TradeAdded};
+use protocol::decimal::{Decimal, Precision};
+use protocol::from_api::*;
+use protocol::kline::KlineInterval;
+use protocol::markets::{load_markets, parse_markets, MarketConfig, MarketStatus, RejectReason};
+use protocol::order::{Kind, Order, OrderOutcome, OrderType, SelfTradePrevention, TimeInForce};
+use protocol::to_api::{
+    AssetBalance, Balances, CancelReason, DepthPayload, ErrorKind, ErrorMessage, Fill,
+    Klines as KlinesPayload, MessageToApi, OpenOrders, OrderCancelled, OrderPlaced, OrderRejected,
+    SelfTradeCancelled,
+};
+use protocol::to_ws::{kline_stream, DepthData, TickerData, TradeData, WsMessage};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use self::redis_manager::OrderUpdate;
-use self::redis_manager::RedisManager;
+pub const BASE_CURRENCY: &str = "INR";
+pub const DEPTH_LEVELS: usize = 100;
+// the markets config built into the binary, used unless MARKETS_CONFIG points elsewhere
+pub const SHIPPED_MARKETS: &str = include_str!("../markets.toml");
+// how long the command loop waits on the bus before polling again
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy, Default)]
 pub struct Balance {
-    available: usize,
-    locked: usize,
+    available: Decimal,
+    locked: Decimal,
+}
+
+impl Balance {
+    fn credit(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        self.available = self
+            .available
+            .checked_add(amount)
+            .ok_or(EngineError::Overflow)?;
+        Ok(())
+    }
+
+    fn lock(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        let available = self.available.checked_sub(amount);
+        let locked = self.locked.checked_add(amount);
+        (self.available, self.locked) = available
+            .zip(locked)
+            .ok_or(EngineError::InsufficientFunds)?;
+        Ok(())
+    }
+
+    fn unlock(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        let locked = self.locked.checked_sub(amount);
+        let available = self.available.checked_add(amount);
+        (self.available, self.locked) = available.zip(locked).ok_or(EngineError::Overflow)?;
+        Ok(())
+    }
+
+    fn spend_locked(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        self.locked = self
+            .locked
+            .checked_sub(amount)
+            .ok_or(EngineError::InsufficientFunds)?;
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Snapshot {
-    orderbooks: Vec<OrderBook>,
+    orderbooks: HashMap<String, OrderBook>,
     balances: HashMap<String, HashMap<String, Balance>>,
+    #[serde(default)]
+    order_ids: Sequence,
+    #[serde(default)]
+    journal_seq: u64, // last journal entry reflected in this snapshot
+    #[serde(default)]
+    depth_update_ids: HashMap<String, u64>,
+    #[serde(default)]
+    tickers: HashMap<String, Ticker>,
+    #[serde(default)]
+    klines: HashMap<String, Klines>,
+    // set when decoded from a layout before klines, whose history the journal has to restore
+    #[serde(skip)]
+    backfill_klines: bool,
+}
+
+// the v2 layout, before depth update ids were kept
+#[derive(Deserialize)]
+struct SnapshotV2 {
+    orderbooks: HashMap<String, OrderBook>,
+    balances: HashMap<String, HashMap<String, Balance>>,
+    order_ids: Sequence,
+    journal_seq: u64,
+}
+
+impl From<SnapshotV2> for Snapshot {
+    fn from(v2: SnapshotV2) -> Self {
+        Snapshot {
+            orderbooks: v2.orderbooks,
+            balances: v2.balances,
+            order_ids: v2.order_ids,
+            journal_seq: v2.journal_seq,
+            depth_update_ids: HashMap::new(),
+            tickers: HashMap::new(),
+            klines: HashMap::new(),
+            backfill_klines: true,
+        }
+    }
+}
+
+// the v3 layout, before tickers were kept
+#[derive(Deserialize)]
+struct SnapshotV3 {
+    orderbooks: HashMap<String, OrderBook>,
+    balances: HashMap<String, HashMap<String, Balance>>,
+    order_ids: Sequence,
+    journal_seq: u64,
+    depth_update_ids: HashMap<String, u64>,
+}
+
+impl From<SnapshotV3> for Snapshot {
+    fn from(v3: SnapshotV3) -> Self {
+        Snapshot {
+            orderbooks: v3.orderbooks,
+            balances: v3.balances,
+            order_ids: v3.order_ids,
+            journal_seq: v3.journal_seq,
+            depth_update_ids: v3.depth_update_ids,
+            tickers: HashMap::new(),
+            klines: HashMap::new(),
+            backfill_klines: true,
+        }
+    }
+}
+
+// the v4 layout, before klines were kept
+#[derive(Deserialize)]
+struct SnapshotV4 {
+    orderbooks: HashMap<String, OrderBook>,
+    balances: HashMap<String, HashMap<String, Balance>>,
+    order_ids: Sequence,
+    journal_seq: u64,
+    depth_update_ids: HashMap<String, u64>,
+    tickers: HashMap<String, Ticker>,
+}
+
+// klines start out from the last 24 hours of trades the tickers still hold, in case the
+// journal no longer goes back far enough to backfill them
+impl From<SnapshotV4> for Snapshot {
+    fn from(v4: SnapshotV4) -> Self {
+        let klines = v4
+            .tickers
+            .iter()
+            .map(|(market, ticker)| {
+                let mut klines = Klines::default();
+                ticker.buckets().for_each(|bucket| klines.merge(bucket));
+                (market.clone(), klines)
+            })
+            .collect();
+        Snapshot {
+            orderbooks: v4.orderbooks,
+            balances: v4.balances,
+            order_ids: v4.order_ids,
+            journal_seq: v4.journal_seq,
+            depth_update_ids: v4.depth_update_ids,
+            tickers: v4.tickers,
+            klines,
+            backfill_klines: true,
+        }
+    }
+}
+
+// v1: the original JSON encoding, v2: bincode, v3: adds depth update ids, v4: adds tickers,
+// v5: adds klines
+impl SnapshotData for Snapshot {
+    const VERSION: u32 = 5;
+
+    fn decode(version: u32, payload: &[u8]) -> Result<Self, SnapshotError> {
+        let decode_error = |e: bincode::Error| SnapshotError::Decode(e.to_string());
+        match version {
+            LEGACY_JSON_VERSION => serde_json::from_slice(payload)
+                .map(|snapshot| Snapshot {
+                    backfill_klines: true,
+                    ..snapshot
+                })
+                .map_err(|e| SnapshotError::Decode(e.to_string())),
+            2 => bincode::deserialize::<SnapshotV2>(payload)
+                .map(Snapshot::from)
+                .map_err(decode_error),
+            3 => bincode::deserialize::<SnapshotV3>(payload)
+                .map(Snapshot::from)
+                .map_err(decode_error),
+            4 => bincode::deserialize::<SnapshotV4>(payload)
+                .map(Snapshot::from)
+                .map_err(decode_error),
+            5 => bincode::deserialize(payload).map_err(decode_error),
+            _ => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Pretty-printed JSON of the snapshot at `path`, or of the newest one in the configured
+    /// snapshot directory, for inspecting state by hand.
+    pub fn export_json(path: Option<&Path>) -> Result<String, SnapshotError> {
+        let snapshot: Snapshot = match path {
+            Some(path) => SnapshotStore::read_file(path)?,
+            None => SnapshotStore::new(SnapshotConfig::from_env())
+                .latest()
+                .ok_or_else(|| {
+                    SnapshotError::Io(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "no readable snapshot found",
+                    ))
+                })?,
+        };
+        serde_json::to_string_pretty(&snapshot).map_err(|e| SnapshotError::Encode(e.to_string()))
+    }
+}
+
+// what a fresh engine starts with before the first journal entry
+fn initial_balances() -> HashMap<String, HashMap<String, Balance>> {
+    let mut asset_balances = HashMap::new();
+    asset_balances.insert(
+        "BTC".to_string(),
+        Balance {
+            available: Decimal::from(10000000),
+            locked: Decimal::ZERO,
+        },
+    );
+    asset_balances.insert(
+        "TATA".to_string(),
+        Balance {
+            available: Decimal::from(10000000),
+            locked: Decimal::ZERO,
+        },
+    );
+    HashMap::from([("default_user".to_string(), asset_balances)])
 }
 
 #[derive(Clone)]
 pub struct Engine {
-    orderbooks: Vec<OrderBook>,
+    orderbooks: HashMap<String, OrderBook>, // keyed by market symbol
 
     // balance should look like this ::
 
@@ -48,175 +269,934 @@ pub struct Engine {
     //             locked: 0
     //         }
     balances: HashMap<String, HashMap<String, Balance>>,
-    redis_manager: Arc<Mutex<RedisManager>>,
+    order_ids: Sequence,
+    bus: Arc<dyn MessageBus>,
+    journal: Arc<Mutex<Journal>>,
+    journal_seq: u64,                       // last journal entry applied
+    depth_update_ids: HashMap<String, u64>, // last depth update id per market, see `DepthData`
+    tickers: HashMap<String, Ticker>,       // keyed by market symbol
+    klines: HashMap<String, Klines>,        // keyed by market symbol
+    command_time: u64, // when the command being applied was accepted, taken from the journal
+    replaying: bool,   // suppresses replies and downstream messages while catching up
+    config_markets: Vec<MarketConfig>, // from the config file, see `add_config_markets`
+    db_updates: u32,   // pushed to the db for the current journal entry, see `push_to_db`
+    snapshots: SnapshotWriter<Snapshot>,
+    snapshot_interval: Duration,
+    last_snapshot: Instant,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
-        let path = Path::new("./snapshot.json");
-        let mut orderbooks = vec![];
-        let mut balances = HashMap::new();
+        let redis_manager =
+            RedisManager::new().unwrap_or_else(|e| panic!("Invalid REDIS_URL: {}", e));
+        Self::with_bus(Arc::new(redis_manager))
+    }
 
-        if path.exists() {
-            let mut file = match File::open(&path) {
-                Ok(file) => file,
-                Err(e) => {
-                    eprintln!("Failed to open the file: {}", e);
-                    return Self {
-                        orderbooks,
-                        balances,
-                        redis_manager: Arc::new(Mutex::new(RedisManager::new().unwrap())),
-                    };
-                }
+    /// Builds the engine on top of any bus, e.g. an `InMemoryBus` in tests. Snapshots, the
+    /// journal and markets are still located through the usual environment variables.
+    pub fn with_bus(bus: Arc<dyn MessageBus>) -> Self {
+        let snapshot_store = SnapshotStore::new(SnapshotConfig::from_env());
+        let snapshot_interval = snapshot_store.config().interval;
+        let mut orderbooks = HashMap::new();
+        let mut balances = initial_balances();
+        let mut order_ids = Sequence::default();
+        let mut journal_seq = 0;
+        let mut depth_update_ids = HashMap::new();
+        let mut tickers = HashMap::new();
+        let mut klines = HashMap::new();
+        let mut backfill_klines = false;
+
+        if let Some(snapshot) = snapshot_store.latest::<Snapshot>() {
+            orderbooks = snapshot.orderbooks;
+            balances = snapshot.balances;
+            order_ids = snapshot.order_ids;
+            journal_seq = snapshot.journal_seq;
+            depth_update_ids = snapshot.depth_update_ids;
+            tickers = snapshot.tickers;
+            klines = snapshot.klines;
+            backfill_klines = snapshot.backfill_klines;
+        }
+
+        // replaying past a corrupt entry would silently diverge from the pre-crash state
+        let journal_path =
+            env::var("JOURNAL_PATH").unwrap_or_else(|_| DEFAULT_JOURNAL_PATH.to_string());
+        let mut journal = Journal::open(Path::new(&journal_path))
+            .unwrap_or_else(|e| panic!("Failed to open journal {}: {}", journal_path, e));
+        journal.advance_to(journal_seq);
+
+        // without its markets the engine would reject every order it is sent
+        let markets = match env::var("MARKETS_CONFIG") {
+            Ok(path) => load_markets(Path::new(&path))
+                .unwrap_or_else(|e| panic!("Failed to load markets from {}: {}", path, e)),
+            Err(_) => parse_markets(SHIPPED_MARKETS)
+                .unwrap_or_else(|e| panic!("Failed to load the shipped markets: {}", e)),
+        };
+
+        let mut engine = Self {
+            orderbooks,
+            balances,
+            order_ids,
+            bus,
+            journal: Arc::new(Mutex::new(journal)),
+            journal_seq,
+            depth_update_ids,
+            tickers,
+            klines,
+            command_time: now_millis(),
+            replaying: false,
+            config_markets: markets,
+            db_updates: 0,
+            snapshots: SnapshotWriter::spawn(snapshot_store),
+            snapshot_interval,
+            last_snapshot: Instant::now(),
+        };
+
+        engine.replay_journal();
+        if backfill_klines {
+            engine.backfill_klines();
+        }
+        engine.add_config_markets();
+        engine.snapshot();
+        engine
+    }
+
+    // rebuilds the candles a snapshot from before klines could not carry by replaying the
+    // whole journal into a scratch engine that starts out like a fresh one
+    fn backfill_klines(&mut self) {
+        let first_seq = match self.journal.lock().unwrap().entries_after(0) {
+            Ok(entries) => entries.first().map(|entry| entry.seq),
+            Err(e) => panic!("Failed to read journal: {}", e),
+        };
+        if first_seq != Some(1) {
+            eprintln!(
+                "Journal does not start at seq 1, klines only cover the tickers' last 24 hours"
+            );
+            return;
+        }
+
+        let mut scratch = Engine {
+            orderbooks: HashMap::new(),
+            balances: initial_balances(),
+            order_ids: Sequence::default(),
+            bus: self.bus.clone(),
+            journal: self.journal.clone(),
+            journal_seq: 0,
+            depth_update_ids: HashMap::new(),
+            tickers: HashMap::new(),
+            klines: HashMap::new(),
+            command_time: now_millis(),
+            replaying: false,
+            config_markets: self.config_markets.clone(),
+            db_updates: 0,
+            snapshots: self.snapshots.clone(),
+            snapshot_interval: self.snapshot_interval,
+            last_snapshot: Instant::now(),
+        };
+        scratch.replay_journal();
+        self.klines = scratch.klines;
+    }
+
+    // config file markets the snapshot and journal do not know yet are journaled like an
+    // admin's AddMarket, so replay never depends on what the file says by then
+    fn add_config_markets(&mut self) {
+        for market in self.config_markets.clone() {
+            if self.orderbooks.contains_key(&market.symbol) {
+                continue;
+            }
+            let message = MessageFromApi::AddMarket {
+                data: market.clone(),
             };
+            if let Err(e) = self.record(&message) {
+                panic!("Failed to journal market {}: {}", market.symbol, e);
+            }
+            self.register_market(market);
+        }
+    }
 
-            let mut contents = String::new();
-            if let Err(e) = file.read_to_string(&mut contents) {
-                eprintln!("Failed to read the file: {}", e);
-                return Self {
-                    orderbooks,
-                    balances,
-                    redis_manager: Arc::new(Mutex::new(RedisManager::new().unwrap())),
-                };
+    // markets restored from a snapshot keep their config and status, the config file only
+    // adds the ones the engine has not seen
+    fn register_market(&mut self, market: MarketConfig) {
+        self.orderbooks
+            .entry(market.symbol.clone())
+            .or_insert_with(|| OrderBook::new(market, 0, Decimal::ZERO));
+    }
+
+    /// Opens a new market at runtime. Existing markets are changed with `set_market_status`.
+    pub fn add_market(&mut self, market: MarketConfig) -> Result<(), EngineError> {
+        market
+            .validate()
+            .map_err(|e| EngineError::InvalidMarket(e.to_string()))?;
+        if self.orderbooks.contains_key(&market.symbol) {
+            return Err(EngineError::MarketExists);
+        }
+        self.register_market(market);
+        Ok(())
+    }
+
+    pub fn set_market_status(
+        &mut self,
+        market: &str,
+        status: MarketStatus,
+    ) -> Result<MarketConfig, EngineError> {
+        let orderbook = self
+            .orderbook_mut(market)
+            .ok_or(EngineError::MarketNotFound)?;
+        orderbook.market.status = status;
+        Ok(orderbook.market.clone())
+    }
+
+    // re-applies every command journaled after the snapshot, in order
+    fn replay_journal(&mut self) {
+        let entries = match self.journal.lock().unwrap().entries_after(self.journal_seq) {
+            Ok(entries) => entries,
+            Err(e) => panic!("Failed to read journal: {}", e),
+        };
+        if let Some(first) = entries.first() {
+            if first.seq != self.journal_seq + 1 {
+                panic!(
+                    "Journal starts at seq {} but the snapshot ends at {}",
+                    first.seq, self.journal_seq
+                );
+            }
+        }
+
+        // journals written before startup journaled its markets assume the config file's
+        if self.orderbooks.is_empty()
+            && entries
+                .first()
+                .is_some_and(|entry| !matches!(entry.command, MessageFromApi::AddMarket { .. }))
+        {
+            for market in self.config_markets.clone() {
+                self.register_market(market);
+            }
+        }
+
+        // an operator's way past an entry that cannot be applied, e.g. `JOURNAL_SKIP=1042`
+        let skipped: HashSet<u64> = env::var("JOURNAL_SKIP")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|seq| seq.trim().parse().ok())
+            .collect();
+
+        self.replaying = true;
+        for entry in entries {
+            self.journal_seq = entry.seq;
+            if skipped.contains(&entry.seq) {
+                eprintln!(
+                    "Skipping journal entry {} listed in JOURNAL_SKIP",
+                    entry.seq
+                );
+                continue;
             }
+            // entries from before timestamps were journaled replay as if accepted now
+            self.command_time = match entry.timestamp {
+                0 => now_millis(),
+                timestamp => timestamp,
+            };
+            let applied = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.apply(entry.command, String::new())
+            }));
+            if applied.is_err() {
+                panic!(
+                    "Journal entry {} cannot be applied, restart with JOURNAL_SKIP={} to replay without it",
+                    entry.seq, entry.seq
+                );
+            }
+        }
+        self.replaying = false;
+    }
+
+    /// Hands a copy of the current state to the snapshot writer. Only called between
+    /// commands, so the copy always matches `journal_seq` exactly.
+    pub fn snapshot(&mut self) {
+        self.snapshots.send(
+            self.journal_seq,
+            Snapshot {
+                orderbooks: self.orderbooks.clone(),
+                balances: self.balances.clone(),
+                order_ids: self.order_ids.clone(),
+                journal_seq: self.journal_seq,
+                depth_update_ids: self.depth_update_ids.clone(),
+                tickers: self.tickers.clone(),
+                klines: self.klines.clone(),
+                backfill_klines: false,
+            },
+        );
+        self.last_snapshot = Instant::now();
+    }
 
-            let snapshot: Snapshot = match serde_json::from_str(&contents) {
-                Ok(snapshot) => snapshot,
+    /// Consumes commands from the bus until the process exits. The engine lock is only taken
+    /// while a command is handled, never while waiting for one.
+    pub fn run(engine: Arc<Mutex<Engine>>) {
+        let bus = engine.lock().unwrap().bus.clone();
+        loop {
+            match bus.receive_command(POLL_TIMEOUT) {
+                Ok(Some(payload)) => engine.lock().unwrap().handle_payload(&payload),
+                Ok(None) => {}
                 Err(e) => {
-                    eprintln!("Failed to parse JSON: {}", e);
-                    return Self {
-                        orderbooks,
-                        balances,
-                        redis_manager: Arc::new(Mutex::new(RedisManager::new().unwrap())),
-                    };
+                    eprintln!("Failed to receive command: {}", e);
+                    thread::sleep(RECONNECT_DELAY);
                 }
-            };
+            }
+        }
+    }
 
-            orderbooks = snapshot.orderbooks;
-            balances = snapshot.balances;
-        } else {
-            orderbooks.push(OrderBook {
-                bids: Vec::new(),
-                asks: Vec::new(),
-                base_asset: String::from("BTC"),
-                quote_asset: String::from("USD"),
-                last_trade_id: 0,
-                current_price: 0,
-                bid_depth: HashMap::new(),
-                ask_depth: HashMap::new(),
-            });
-
-            balances.insert("default_user".to_string(), {
-                let mut asset_balances = HashMap::new();
-                asset_balances.insert(
-                    "BTC".to_string(),
-                    Balance {
-                        available: 10000000,
-                        locked: 0,
-                    },
+    /// Decodes one `{clientId, message}` envelope and processes it. Payloads that do not
+    /// decode are answered with an error when they carry a usable client id, and dropped
+    /// otherwise.
+    pub fn handle_payload(&mut self, payload: &str) {
+        let value: serde_json::Value = match serde_json::from_str(payload) {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("Dropping unparsable message: {}", e);
+                return;
+            }
+        };
+        let client_id = value
+            .get("clientId")
+            .and_then(|id| id.as_str())
+            .map(String::from);
+
+        match serde_json::from_value::<IncomingMessage>(value) {
+            Ok(incoming) => self.process(incoming.message, incoming.client_id),
+            Err(e) => match client_id {
+                Some(client_id) => {
+                    self.send_to_api(
+                        client_id,
+                        &Self::error_message(EngineError::MalformedMessage(e.to_string())),
+                    );
+                }
+                None => eprintln!("Dropping message without a client id: {}", e),
+            },
+        }
+    }
+
+    /// Journals state-changing commands, then applies the message and replies to `client_id`.
+    pub fn process(&mut self, message: MessageFromApi, client_id: String) {
+        let is_command = message.is_command();
+        if is_command {
+            if let Err(e) = self.bus.status() {
+                eprintln!("Refusing command: {}", e);
+                self.send_to_api(
+                    client_id,
+                    &Self::error_message(EngineError::Bus(e.to_string())),
                 );
-                asset_balances.insert(
-                    "TATA".to_string(),
-                    Balance {
-                        available: 10000000,
-                        locked: 0,
+                return;
+            }
+            if let Err(e) = self.record(&message) {
+                eprintln!("Failed to journal command: {}", e);
+                self.send_to_api(client_id, &Self::error_message(e));
+                return;
+            }
+        }
+        let reply_to = client_id.clone();
+        let applied = panic::catch_unwind(AssertUnwindSafe(|| self.apply(message, client_id)));
+        if applied.is_err() {
+            if is_command {
+                self.rebuild_without(self.journal_seq);
+            }
+            self.send_to_api(reply_to, &Self::error_message(EngineError::CommandFailed));
+        }
+    }
+
+    // a command that panicked part way through left the state half applied, and would panic
+    // again on every replay: its entry comes back out of the journal and the state is rebuilt
+    // from the last snapshot and the journal without it
+    fn rebuild_without(&mut self, seq: u64) {
+        eprintln!(
+            "Journal entry {} failed to apply, rebuilding without it",
+            seq
+        );
+        if let Err(e) = self.journal.lock().unwrap().discard(seq) {
+            panic!("Failed to discard journal entry {}: {}", seq, e);
+        }
+        *self = Engine::with_bus(self.bus.clone());
+    }
+
+    /// Durably appends a command to the journal, stamped with the current time that trades
+    /// it causes will carry. Must succeed before the command is applied.
+    fn record(&mut self, message: &MessageFromApi) -> Result<(), EngineError> {
+        if self.last_snapshot.elapsed() >= self.snapshot_interval {
+            self.snapshot();
+        }
+        let timestamp = now_millis();
+        let seq = self
+            .journal
+            .lock()
+            .unwrap()
+            .append(message, timestamp)
+            .map_err(|e| EngineError::Journal(e.to_string()))?;
+        self.journal_seq = seq;
+        self.command_time = timestamp;
+        self.db_updates = 0;
+        Ok(())
+    }
+
+    fn apply(&mut self, message: MessageFromApi, client_id: String) {
+        match message {
+            MessageFromApi::CreateOrder { data } => {
+                let precision = self.precision(&data.market);
+                let msg = match self.create_order(data) {
+                    Ok(created) if created.outcome.is_rejected() => MessageToApi::OrderCancelled {
+                        payload: OrderCancelled {
+                            order_id: created.order_id,
+                            executed_qty: precision.quantity(Decimal::ZERO),
+                            remaining_qty: precision.quantity(created.quantity),
+                            reason: match created.outcome {
+                                OrderOutcome::PostOnlyRejected => CancelReason::PostOnlyRejected,
+                                _ => CancelReason::Killed,
+                            },
+                        },
+                    },
+                    Ok(created) => MessageToApi::OrderPlaced {
+                        payload: OrderPlaced {
+                            order_id: created.order_id,
+                            outcome: created.outcome,
+                            resting_price: created.resting_price.map(|p| precision.price(p)),
+                            executed_qty: precision.quantity(created.executed_qty),
+                            fills: created
+                                .fills
+                                .iter()
+                                .map(|fill| Fill {
+                                    price: precision.price(fill.price),
+                                    qty: precision.quantity(fill.quantity),
+                                    trade_id: fill.tradeid,
+                                })
+                                .collect(),
+                            self_trade_qty: precision.quantity(created.self_trade_qty),
+                            self_trade_cancels: created
+                                .self_trade_cancels
+                                .iter()
+                                .map(|cancel| SelfTradeCancelled {
+                                    order_id: cancel.order.order_id.clone(),
+                                    cancelled_qty: precision.quantity(cancel.cancelled_qty),
+                                })
+                                .collect(),
+                            cancelled_qty: precision.quantity(created.cancelled_qty),
+                        },
+                    },
+                    Err(EngineError::Rejected(reason)) => MessageToApi::OrderRejected {
+                        payload: OrderRejected {
+                            reason,
+                            message: reason.to_string(),
+                        },
+                    },
+                    Err(e) => Self::error_message(e),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::CancelOrder { data } => {
+                let precision = self.precision(&data.market);
+                let msg = match self.cancel_order(&data.order_id, &data.user_id, &data.market) {
+                    Ok(order) => MessageToApi::OrderCancelled {
+                        payload: OrderCancelled {
+                            order_id: order.order_id,
+                            executed_qty: precision.quantity(order.filled),
+                            remaining_qty: precision.quantity(order.quantity - order.filled),
+                            reason: CancelReason::Requested,
+                        },
+                    },
+                    Err(e) => Self::error_message(e),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::OnRamp { data } => {
+                let msg = match self.on_ramp(&data.user_id, data.amount) {
+                    Ok(()) => MessageToApi::Balances {
+                        payload: self.balances(&data.user_id),
+                    },
+                    Err(e) => Self::error_message(e),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::GetBalances { data } => {
+                let balances = self.balances(&data.user_id);
+                self.send_to_api(client_id, &MessageToApi::Balances { payload: balances });
+            }
+            MessageFromApi::GetDepth { data } => {
+                let depth = match self.orderbook(&data.market) {
+                    Some(orderbook) => {
+                        let depth = orderbook.get_depth(DEPTH_LEVELS);
+                        let last_update_id = self.depth_update_id(&data.market);
+                        DepthPayload::new(&depth, orderbook.market.precision, last_update_id)
+                    }
+                    None => {
+                        eprintln!("No orderbook found for market {}", data.market);
+                        DepthPayload::default()
+                    }
+                };
+                self.send_to_api(client_id, &MessageToApi::Depth { payload: depth });
+            }
+            MessageFromApi::GetTicker { data } => {
+                let msg = match self.ticker(&data.market, now_millis()) {
+                    Some(ticker) => MessageToApi::Ticker { payload: ticker },
+                    None => Self::error_message(EngineError::MarketNotFound),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::GetKlines { data } => {
+                let start = data.start_time.unwrap_or(0);
+                let end = data.end_time.unwrap_or(u64::MAX);
+                let msg = match self.orderbook(&data.market) {
+                    Some(orderbook) => {
+                        let precision = orderbook.market.precision;
+                        let klines = self
+                            .klines
+                            .get(&data.market)
+                            .map(|klines| klines.range(data.interval, start, end))
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|candle| candle.kline(&data.market, data.interval, precision))
+                            .collect();
+                        MessageToApi::Klines {
+                            payload: KlinesPayload { klines },
+                        }
+                    }
+                    None => Self::error_message(EngineError::MarketNotFound),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::GetOpenOrders { data } => {
+                let orders = match self.orderbook(&data.market) {
+                    Some(orderbook) => orderbook.get_open_orders(&data.user_id),
+                    None => {
+                        eprintln!("No orderbook found for market {}", data.market);
+                        vec![]
+                    }
+                };
+                self.send_to_api(
+                    client_id,
+                    &MessageToApi::OpenOrders {
+                        payload: OpenOrders { orders },
                     },
                 );
-                asset_balances
-            });
+            }
+            MessageFromApi::AddMarket { data } => {
+                let msg = match self.add_market(data.clone()) {
+                    Ok(()) => MessageToApi::MarketUpdated { payload: data },
+                    Err(e) => Self::error_message(e),
+                };
+                self.send_to_api(client_id, &msg);
+            }
+            MessageFromApi::SetMarketStatus { data } => {
+                let msg = match self.set_market_status(&data.market, data.status) {
+                    Ok(market) => MessageToApi::MarketUpdated { payload: market },
+                    Err(e) => Self::error_message(e),
+                };
+                self.send_to_api(client_id, &msg);
+            }
         }
+    }
 
-        let engine = Self {
-            orderbooks,
-            balances,
-            redis_manager: Arc::new(Mutex::new(RedisManager::new().unwrap())), // Initialize RedisManager here
+    fn error_message(e: EngineError) -> MessageToApi {
+        MessageToApi::Error {
+            payload: ErrorMessage {
+                kind: e.kind(),
+                message: e.to_string(),
+            },
+        }
+    }
+
+    fn orderbook(&self, market: &str) -> Option<&OrderBook> {
+        self.orderbooks.get(market)
+    }
+
+    fn orderbook_mut(&mut self, market: &str) -> Option<&mut OrderBook> {
+        self.orderbooks.get_mut(market)
+    }
+
+    // 24h statistics for a known market, empty until its first trade
+    fn ticker(&self, market: &str, now: u64) -> Option<TickerData> {
+        let precision = self.orderbook(market)?.market.precision;
+        let data = match self.tickers.get(market) {
+            Some(ticker) => ticker.data(market, precision, now),
+            None => Ticker::default().data(market, precision, now),
         };
-        engine.start_snapshot_save();
-        engine
+        Some(data)
     }
 
-    pub fn start_snapshot_save(&self) {
-        let orderbooks = self.orderbooks.clone();
-        let balances = self.balances.clone();
+    fn depth_update_id(&self, market: &str) -> u64 {
+        self.depth_update_ids.get(market).copied().unwrap_or(0)
+    }
 
-        thread::spawn(move || loop {
-            thread::sleep(Duration::from_secs(3));
-            let snapshot = Snapshot {
-                orderbooks: orderbooks.clone(),
-                balances: balances.clone(),
-            };
-            match serde_json::to_string(&snapshot) {
-                Ok(json) => {
-                    let mut file = match OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .open("./snapshot.json")
-                    {
-                        Ok(file) => file,
-                        Err(e) => {
-                            eprintln!("Failed to open the file for writing: {}", e);
-                            continue;
-                        }
-                    };
+    // formatting precision for replies, falling back to the default for unknown markets
+    fn precision(&self, market: &str) -> Precision {
+        self.orderbook(market)
+            .map(|orderbook| orderbook.market.precision)
+            .unwrap_or_default()
+    }
 
-                    if let Err(e) = file.write_all(json.as_bytes()) {
-                        eprintln!("Failed to write to the file: {}", e);
-                    }
+    // downstream already saw everything being replayed
+    fn bus(&self) -> Option<&dyn MessageBus> {
+        (!self.replaying).then_some(&*self.bus)
+    }
+
+    fn send_to_api(&self, client_id: String, msg: &MessageToApi) {
+        let Some(bus) = self.bus() else {
+            return;
+        };
+        if let Err(e) = bus.send_to_api(&client_id, msg) {
+            eprintln!("Failed to send message to api: {}", e);
+        }
+    }
+
+    pub fn create_order(&mut self, input: CreateOrder) -> Result<CreatedOrder, EngineError> {
+        let market = input.market;
+        let side = input.side;
+        let userid = input.user_id;
+        let orderbook = self.orderbook(&market).ok_or(EngineError::MarketNotFound)?;
+        if !orderbook.market.status.accepts_orders() {
+            return Err(RejectReason::MarketNotTrading.into());
+        }
+        let precision = orderbook.market.precision;
+        let rules = orderbook.market.rules;
+        let base_asset = orderbook.market.base_asset.clone();
+        let quote_asset = orderbook.market.quote_asset.clone();
+
+        let prices = [
+            Some(input.price),
+            input.protection_price,
+            input.quote_quantity,
+        ];
+        if !prices
+            .iter()
+            .flatten()
+            .all(|p| p.fits(precision.price_decimals))
+            || !input.quantity.fits(precision.quantity_decimals)
+        {
+            return Err(RejectReason::InvalidPrecision.into());
+        }
+
+        // market orders lock exactly what sweeping the book would cost, capped by the protection price
+        let (price, quantity, required) = match input.order_type {
+            OrderType::Limit => {
+                rules.check_price(input.price)?;
+                rules.check_quantity(input.quantity)?;
+                let notional = input
+                    .price
+                    .checked_mul(input.quantity)
+                    .ok_or(EngineError::Overflow)?;
+                rules.check_notional(notional)?;
+                if !orderbook.has_depth_room(side, input.quantity) {
+                    return Err(EngineError::Overflow);
                 }
-                Err(e) => {
-                    eprintln!("Failed to serialize snapshot: {}", e);
+                let required = match side {
+                    Kind::BUY => notional,
+                    Kind::SELL => input.quantity,
+                };
+                (input.price, input.quantity, required)
+            }
+            OrderType::Market => {
+                match input.quote_quantity {
+                    Some(quote_quantity) => rules.check_notional(quote_quantity)?,
+                    None => rules.check_quantity(input.quantity)?,
+                }
+                let limit = orderbook.protection_price(
+                    side,
+                    input.protection_price,
+                    input.max_slippage_bps,
+                );
+                // the user's own orders cannot fill it unless self-trade prevention is off
+                let skip_user = (input.self_trade_prevention != SelfTradePrevention::None)
+                    .then_some(userid.as_str());
+                let sweep =
+                    orderbook.sweep(side, skip_user, input.quantity, input.quote_quantity, limit);
+                if sweep.quantity.is_zero() {
+                    return Err(RejectReason::NoLiquidity.into());
                 }
+                let required = match side {
+                    Kind::BUY => sweep.quote_quantity,
+                    Kind::SELL => sweep.quantity,
+                };
+                // a quote amount buys whatever base quantity it can, a base quantity stays
+                // as asked so a shortfall shows up as a cancelled remainder
+                let quantity = match input.quote_quantity {
+                    Some(_) => sweep.quantity,
+                    None => input.quantity,
+                };
+                (sweep.worst_price, quantity, required)
             }
-        });
-    }
+        };
+        let locked_asset = match side {
+            Kind::BUY => &quote_asset,
+            Kind::SELL => &base_asset,
+        };
 
-    pub fn process(self, message: MessageFromApi, client_id: String) {
-        match message {
-            MessageFromApi::CreateOrder => {
-                todo!()
+        self.check_and_lock_funds(&userid, locked_asset, required)
+            .map_err(|e| match e {
+                EngineError::InsufficientFunds => RejectReason::InsufficientFunds.into(),
+                e => e,
+            })?;
+        let order_id = self.order_ids.next_id();
+
+        let orderbook = self
+            .orderbook_mut(&market)
+            .ok_or(EngineError::MarketNotFound)?;
+        let mut order = Order {
+            order_id: order_id.to_string(),
+            price,
+            quantity,
+            filled: Decimal::ZERO,
+            side,
+            user_id: userid.clone(),
+        };
+        let time_in_force = match input.order_type {
+            OrderType::Limit => input.time_in_force,
+            OrderType::Market => TimeInForce::Ioc,
+        };
+        let fill_result = orderbook.add_order(
+            &mut order,
+            time_in_force,
+            input.self_trade_prevention,
+            Some(required),
+        );
+
+        self.update_balance(&userid, &base_asset, &quote_asset, side, &fill_result.fills)?;
+        for cancel in &fill_result.self_trade_cancels {
+            self.unlock_funds(
+                &base_asset,
+                &quote_asset,
+                &cancel.order,
+                cancel.cancelled_qty,
+            )?;
+        }
+
+        // release whatever was locked but neither spent nor backing a resting remainder
+        let spent = match side {
+            Kind::BUY => fill_result.fills.iter().map(|f| f.price * f.quantity).sum(),
+            Kind::SELL => fill_result.executedqty,
+        };
+        let resting = match (fill_result.outcome.is_resting(), side) {
+            (false, _) => Decimal::ZERO,
+            (true, Kind::BUY) => order.price * (order.quantity - fill_result.executedqty),
+            (true, Kind::SELL) => order.quantity - fill_result.executedqty,
+        };
+        let unused = required
+            .checked_sub(spent)
+            .and_then(|left| left.checked_sub(resting))
+            .ok_or(EngineError::InsufficientFunds)?;
+        self.unlock(&userid, locked_asset, unused)?;
+
+        let cancelled_qty = match fill_result.outcome.is_resting() {
+            true => Decimal::ZERO,
+            false => order.quantity - fill_result.executedqty,
+        };
+
+        self.update_db_orders(&order, &fill_result, cancelled_qty, &market);
+        self.create_db_trades(&fill_result.fills, &market, side);
+        self.publish_ws_trade(&fill_result.fills, &market, precision, side);
+
+        // levels emptied or reduced by fills and self-trade cancels, plus any resting remainder
+        let mut changed: Vec<(Kind, Decimal)> = fill_result
+            .fills
+            .iter()
+            .map(|fill| (side.opposite(), fill.price))
+            .chain(
+                fill_result
+                    .self_trade_cancels
+                    .iter()
+                    .map(|cancel| (cancel.order.side, cancel.order.price)),
+            )
+            .collect();
+        if fill_result.outcome.is_resting() {
+            changed.push((side, order.price));
+        }
+        self.publish_ws_depth_update(&market, &changed);
+
+        if !fill_result.fills.is_empty() {
+            let now = self.command_time;
+            let ticker = self.tickers.entry(market.clone()).or_default();
+            let klines = self.klines.entry(market.clone()).or_default();
+            for fill in &fill_result.fills {
+                ticker.record(now, fill.tradeid, fill.price, fill.quantity);
+                klines.record(now, fill.price, fill.quantity);
             }
+            self.publish_ws_ticker(&market, now);
+            self.publish_ws_klines(&market);
         }
+
+        Ok(CreatedOrder {
+            executed_qty: fill_result.executedqty,
+            fills: fill_result.fills,
+            order_id: order.order_id,
+            quantity,
+            outcome: fill_result.outcome,
+            resting_price: fill_result.outcome.is_resting().then_some(order.price),
+            cancelled_qty,
+            self_trade_qty: fill_result.self_trade_qty,
+            self_trade_cancels: fill_result.self_trade_cancels,
+        })
     }
 
-    pub fn create_order(
-        &self,
-        market: Market,
-        price: usize,
-        qty: usize,
+    pub fn cancel_order(
+        &mut self,
+        order_id: &str,
+        user_id: &str,
+        market: &str,
+    ) -> Result<Order, EngineError> {
+        let orderbook = self
+            .orderbook_mut(market)
+            .ok_or(EngineError::MarketNotFound)?;
+        if !orderbook.market.status.accepts_cancels() {
+            return Err(EngineError::MarketNotTrading);
+        }
+
+        let order = orderbook
+            .get_order(order_id)
+            .cloned()
+            .ok_or(EngineError::OrderNotFound)?;
+        if order.user_id != user_id {
+            return Err(EngineError::NotOrderOwner);
+        }
+
+        match order.side {
+            Kind::BUY => orderbook.cancel_bid(&order),
+            Kind::SELL => orderbook.cancel_ask(&order),
+        };
+        let base_asset = orderbook.market.base_asset.clone();
+        let quote_asset = orderbook.market.quote_asset.clone();
+
+        self.unlock_funds(
+            &base_asset,
+            &quote_asset,
+            &order,
+            order.quantity - order.filled,
+        )?;
+
+        self.publish_ws_depth_update(market, &[(order.side, order.price)]);
+
+        Ok(order)
+    }
+
+    fn balance_mut(&mut self, user_id: &str, asset: &str) -> &mut Balance {
+        self.balances
+            .entry(user_id.to_string())
+            .or_default()
+            .entry(asset.to_string())
+            .or_default()
+    }
+
+    // locks the quote asset for buys and the base asset for sells before the order hits the book
+    fn check_and_lock_funds(
+        &mut self,
+        user_id: &str,
+        asset: &str,
+        required: Decimal,
+    ) -> Result<(), EngineError> {
+        self.balance_mut(user_id, asset).lock(required)
+    }
+
+    // settles every fill between the taker and the maker
+    fn update_balance(
+        &mut self,
+        user_id: &str,
+        base_asset: &str,
+        quote_asset: &str,
         side: Kind,
-        userid: String,
-    ) -> CreatedOrder {
-        let orderbook = self.orderbooks.iter().find(|o| o.ticker() == market);
+        fills: &[Fills],
+    ) -> Result<(), EngineError> {
+        for fill in fills {
+            let quote_amount = fill.price * fill.quantity;
+            let (buyer, seller) = match side {
+                Kind::BUY => (user_id, fill.other_userid.as_str()),
+                Kind::SELL => (fill.other_userid.as_str(), user_id),
+            };
+            self.balance_mut(buyer, quote_asset)
+                .spend_locked(quote_amount)?;
+            self.balance_mut(buyer, base_asset).credit(fill.quantity)?;
+
+            self.balance_mut(seller, base_asset)
+                .spend_locked(fill.quantity)?;
+            self.balance_mut(seller, quote_asset).credit(quote_amount)?;
+        }
+        Ok(())
+    }
+
+    // releases what a resting order has locked for `qty` of its unfilled quantity
+    fn unlock_funds(
+        &mut self,
+        base_asset: &str,
+        quote_asset: &str,
+        order: &Order,
+        qty: Decimal,
+    ) -> Result<(), EngineError> {
+        match order.side {
+            Kind::BUY => self.unlock(&order.user_id, quote_asset, order.price * qty),
+            Kind::SELL => self.unlock(&order.user_id, base_asset, qty),
+        }
+    }
 
-        if let Some(orderbook) = orderbook {
-            let (base_asset, quote_asset) = market.assets();
+    fn unlock(&mut self, user_id: &str, asset: &str, amount: Decimal) -> Result<(), EngineError> {
+        self.balance_mut(user_id, asset).unlock(amount)
+    }
+
+    pub fn balances(&self, user_id: &str) -> Balances {
+        let balances = self
+            .balances
+            .get(user_id)
+            .map(|assets| {
+                assets
+                    .iter()
+                    .map(|(asset, balance)| {
+                        let balance = AssetBalance {
+                            available: balance.available.to_string(),
+                            locked: balance.locked.to_string(),
+                        };
+                        (asset.clone(), balance)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Balances {
+            user_id: user_id.to_string(),
+            balances,
+        }
+    }
+
+    /// Credits `amount` of the base currency. Refused once everyone's holdings of it together
+    /// would no longer fit a `Decimal`: settlement only moves funds between users, so keeping
+    /// the total in range keeps every balance it credits in range too.
+    pub fn on_ramp(&mut self, user_id: &str, amount: Decimal) -> Result<(), EngineError> {
+        let supply: u128 = self
+            .balances
+            .values()
+            .filter_map(|assets| assets.get(BASE_CURRENCY))
+            .map(|balance| balance.available.units() as u128 + balance.locked.units() as u128)
+            .sum();
+        if supply + amount.units() as u128 > u64::MAX as u128 {
+            return Err(EngineError::Overflow);
         }
+        self.balance_mut(user_id, BASE_CURRENCY).credit(amount)
     }
 
     pub fn update_db_orders(
-        &self,
-        order: Order,
-        executed_qty: usize,
-        fills: Vec<Fills>,
-        market: Market,
+        &mut self,
+        order: &Order,
+        fill_result: &Fillresult,
+        cancelled_qty: Decimal,
+        market: &str,
     ) {
-        let redis_manager = self.redis_manager.lock().unwrap();
-
         let data = OrderUpdate {
-            order_id: order.order_id,
-            executed_qty,
-            market: Some(market),
+            order_id: order.order_id.clone(),
+            executed_qty: fill_result.executedqty,
+            market: Some(market.to_string()),
             price: Some(order.price),
             quantity: Some(order.quantity),
             side: Some(order.side),
+            // self-trade prevention already took its share out of `quantity`
+            cancelled_qty: Some(cancelled_qty).filter(|qty| !qty.is_zero()),
         };
 
-        let msg = redis_manager::DbMessage::OrderUpdate { data };
-        if let Err(e) = redis_manager.push_message(&msg) {
-            eprintln!("Failed to push message to Redis: {}", e);
-        }
+        let msg = DbMessage::OrderUpdate { data };
+        self.push_to_db(msg);
 
-        fills.iter().map(|fill| {
+        for fill in &fill_result.fills {
             let data = OrderUpdate {
                 order_id: fill.marker_userid.to_owned(),
                 executed_qty: fill.quantity,
@@ -224,46 +1204,395 @@ impl Engine {
                 price: None,
                 quantity: None,
                 side: None,
+                cancelled_qty: None,
             };
-            let msg = redis_manager::DbMessage::OrderUpdate { data };
-            if let Err(e) = redis_manager.push_message(&msg) {
-                eprintln!("Failed to push message to Redis: {}", e);
+            let msg = DbMessage::OrderUpdate { data };
+            self.push_to_db(msg);
+        }
+
+        for cancel in &fill_result.self_trade_cancels {
+            let data = OrderUpdate {
+                order_id: cancel.order.order_id.clone(),
+                executed_qty: cancel.order.filled,
+                market: None,
+                price: None,
+                quantity: Some(cancel.order.quantity),
+                side: None,
+                cancelled_qty: Some(cancel.cancelled_qty),
             };
-        });
+            let msg = DbMessage::OrderUpdate { data };
+            self.push_to_db(msg);
+        }
     }
 
-    pub fn create_db_trades(&self, fills: Vec<Fills>, market: Market, user_id: String) {
-        let redis_manager = self.redis_manager.lock().unwrap();
-        fills.iter().map(|fill| {
+    pub fn create_db_trades(&mut self, fills: &[Fills], market: &str, taker_side: Kind) {
+        for fill in fills {
+            // cannot overflow: every fill's notional is bounded by the checked amount locked for it
             let trade_added = TradeAdded {
-                market: market.to_owned(),
+                market: market.to_string(),
                 id: fill.tradeid.to_string(),
-                is_buyer_maker: fill.other_userid == user_id,
+                is_buyer_maker: taker_side == Kind::SELL,
                 price: fill.price,
                 quantity: fill.quantity,
-                quotequantity: &fill.price * &fill.quantity,
-                timestamp: 000,
+                quotequantity: fill.price * fill.quantity,
+                timestamp: self.command_time,
             };
 
-            let msg = redis_manager::DbMessage::TradeAdded { data: trade_added };
-            if let Err(e) = redis_manager.push_message(&msg) {
-                eprintln!("Failed to push message to Redis: {}", e);
+            let msg = DbMessage::TradeAdded { data: trade_added };
+            self.push_to_db(msg);
+        }
+    }
+
+    // ids follow the journal entry being applied, so every retried copy of an update carries
+    // the same one
+    fn push_to_db(&mut self, message: DbMessage) {
+        self.db_updates += 1;
+        let entry = DbEntry {
+            id: format!("{}:{}", self.journal_seq, self.db_updates),
+            message,
+        };
+        let Some(bus) = self.bus() else {
+            return;
+        };
+        if let Err(e) = bus.push_to_db(&entry) {
+            eprintln!("Failed to push message to db: {}", e);
+        }
+    }
+
+    // sends the new total at every changed price level, "0" once a level is gone. Update ids
+    // advance during replay too, so they pick up exactly where they were before a restart.
+    pub fn publish_ws_depth_update(&mut self, market: &str, changed: &[(Kind, Decimal)]) {
+        let Some(orderbook) = self.orderbook(market) else {
+            return;
+        };
+        let precision = orderbook.market.precision;
+        let levels = |side: Kind| {
+            let prices: BTreeSet<Decimal> = changed
+                .iter()
+                .filter(|(s, _)| *s == side)
+                .map(|&(_, price)| price)
+                .collect();
+            let levels: Vec<(String, String)> = prices
+                .into_iter()
+                .map(|price| {
+                    let quantity = orderbook.depth_at(side, price);
+                    (precision.price(price), precision.quantity(quantity))
+                })
+                .collect();
+            (!levels.is_empty()).then_some(levels)
+        };
+        let (b, a) = (levels(Kind::BUY), levels(Kind::SELL));
+        let updates = b.iter().chain(&a).map(Vec::len).sum::<usize>() as u64;
+        if updates == 0 {
+            return;
+        }
+
+        let last_update_id = self.depth_update_ids.entry(market.to_string()).or_default();
+        let first_update_id = *last_update_id + 1;
+        *last_update_id += updates;
+        let msg = WsMessage::DepthUpdateMessage {
+            data: DepthData {
+                b,
+                a,
+                e: "depth".to_string(),
+                first_update_id,
+                last_update_id: *last_update_id,
+            },
+        };
+
+        let Some(bus) = self.bus() else {
+            return;
+        };
+        if let Err(e) = bus.publish_to_ws(&format!("depth@{}", market), &msg) {
+            eprintln!("Failed to publish message to ws: {}", e);
+        };
+    }
+
+    pub fn publish_ws_ticker(&self, market: &str, now: u64) {
+        let (Some(bus), Some(data)) = (self.bus(), self.ticker(market, now)) else {
+            return;
+        };
+        let msg = WsMessage::TickerUpdateMessage { data };
+        if let Err(e) = bus.publish_to_ws(&format!("ticker@{}", market), &msg) {
+            eprintln!("Failed to publish message to ws: {}", e);
+        };
+    }
+
+    // the open candle of every interval, after a trade changed them all
+    pub fn publish_ws_klines(&self, market: &str) {
+        let (Some(bus), Some(orderbook), Some(klines)) =
+            (self.bus(), self.orderbook(market), self.klines.get(market))
+        else {
+            return;
+        };
+        let precision = orderbook.market.precision;
+        for interval in KlineInterval::ALL {
+            let Some(candle) = klines.latest(interval) else {
+                continue;
             };
-        });
+            let msg = WsMessage::KlineUpdateMessage {
+                data: candle.kline(market, interval, precision),
+            };
+            if let Err(e) = bus.publish_to_ws(&kline_stream(market, interval), &msg) {
+                eprintln!("Failed to publish message to ws: {}", e);
+            };
+        }
     }
 
-    pub fn publish_ws_trade(fills: Vec<Fills>, market: Market, userid: String) {
-        let redis_manager = self.redis_manager.lock().unwrap();
-        fills.iter().map(|fill| {
-            if let Err(e) = redis_manager.push_message(&msg) {
-                eprintln!("Failed to push message to Redis: {}", e);
+    pub fn publish_ws_trade(
+        &self,
+        fills: &[Fills],
+        market: &str,
+        precision: Precision,
+        taker_side: Kind,
+    ) {
+        let Some(bus) = self.bus() else {
+            return;
+        };
+        for fill in fills {
+            let msg = WsMessage::TradeAddedMessage {
+                data: TradeData {
+                    e: "trade".to_string(),
+                    t: fill.tradeid,
+                    m: taker_side == Kind::SELL,
+                    p: precision.price(fill.price),
+                    q: precision.quantity(fill.quantity),
+                    s: market.to_string(),
+                    timestamp: self.command_time,
+                },
+            };
+            if let Err(e) = bus.publish_to_ws(&format!("trade@{}", market), &msg) {
+                eprintln!("Failed to publish message to ws: {}", e);
             };
-        });
+        }
     }
 }
 
 pub struct CreatedOrder {
-    executed_qty: usize,
-    fills: Vec<Fills>,
-    order_id: String,
+    pub executed_qty: Decimal,
+    pub fills: Vec<Fills>,
+    pub order_id: String,
+    pub quantity: Decimal,
+    pub outcome: OrderOutcome,
+    pub resting_price: Option<Decimal>, // after a post-only slide, if the order rests
+    pub cancelled_qty: Decimal,         // unfilled remainder that does not rest
+    pub self_trade_qty: Decimal,
+    pub self_trade_cancels: Vec<SelfTradeCancel>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    MarketNotFound,
+    MarketExists,
+    MarketNotTrading,
+    InvalidMarket(String),
+    Journal(String),
+    OrderNotFound,
+    InsufficientFunds,
+    NotOrderOwner,
+    Overflow,
+    MalformedMessage(String),
+    CommandFailed,
+    Bus(String),
+    Rejected(RejectReason),
+}
+
+impl EngineError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            EngineError::MarketNotFound | EngineError::OrderNotFound => ErrorKind::NotFound,
+            EngineError::NotOrderOwner => ErrorKind::Forbidden,
+            EngineError::MarketExists | EngineError::MarketNotTrading => ErrorKind::Conflict,
+            EngineError::Bus(_) => ErrorKind::Unavailable,
+            EngineError::Journal(_) | EngineError::CommandFailed => ErrorKind::Internal,
+            EngineError::InvalidMarket(_)
+            | EngineError::InsufficientFunds
+            | EngineError::Overflow
+            | EngineError::MalformedMessage(_)
+            | EngineError::Rejected(_) => ErrorKind::Invalid,
+        }
+    }
+}
+
+impl From<RejectReason> for EngineError {
+    fn from(reason: RejectReason) -> Self {
+        EngineError::Rejected(reason)
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::MarketNotFound => write!(f, "market not found"),
+            EngineError::MarketExists => write!(f, "market already exists"),
+            EngineError::MarketNotTrading => write!(f, "market is not accepting this request"),
+            EngineError::InvalidMarket(e) => write!(f, "{}", e),
+            EngineError::Journal(e) => write!(f, "failed to journal command: {}", e),
+            EngineError::OrderNotFound => write!(f, "order not found"),
+            EngineError::InsufficientFunds => write!(f, "insufficient funds"),
+            EngineError::NotOrderOwner => write!(f, "order belongs to another user"),
+            EngineError::Overflow => write!(f, "amount out of range"),
+            EngineError::MalformedMessage(e) => write!(f, "malformed message: {}", e),
+            EngineError::CommandFailed => write!(f, "command could not be applied"),
+            EngineError::Bus(e) => write!(f, "engine is not accepting commands: {}", e),
+            EngineError::Rejected(reason) => write!(f, "order rejected: {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::DEFAULT_SNAPSHOT_RETAIN;
+
+    fn d(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    // a TATA_INR book with a resting bid, a trade in its ticker and klines, and ids in use
+    fn sample() -> Snapshot {
+        let market = parse_markets(SHIPPED_MARKETS)
+            .unwrap()
+            .into_iter()
+            .find(|market| market.symbol == "TATA_INR")
+            .unwrap();
+        let mut orderbook = OrderBook::new(market, 4, d("101.5"));
+        let mut bid = Order {
+            order_id: "1".to_string(),
+            price: d("100"),
+            quantity: d("5"),
+            filled: Decimal::ZERO,
+            side: Kind::BUY,
+            user_id: "alice".to_string(),
+        };
+        orderbook.add_order(&mut bid, TimeInForce::Gtc, SelfTradePrevention::None, None);
+
+        let mut order_ids = Sequence::default();
+        order_ids.next_id();
+        let mut ticker = Ticker::default();
+        ticker.record(1_700_000_000_000, 4, d("101.5"), d("2"));
+        let mut klines = Klines::default();
+        klines.record(1_700_000_000_000, d("101.5"), d("2"));
+
+        Snapshot {
+            orderbooks: HashMap::from([("TATA_INR".to_string(), orderbook)]),
+            balances: initial_balances(),
+            order_ids,
+            journal_seq: 7,
+            depth_update_ids: HashMap::from([("TATA_INR".to_string(), 3)]),
+            tickers: HashMap::from([("TATA_INR".to_string(), ticker)]),
+            klines: HashMap::from([("TATA_INR".to_string(), klines)]),
+            backfill_klines: false,
+        }
+    }
+
+    fn store(name: &str) -> SnapshotStore {
+        let dir = env::temp_dir().join(format!("snapshot-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        SnapshotStore::new(SnapshotConfig {
+            dir,
+            interval: Duration::from_secs(1),
+            retain: DEFAULT_SNAPSHOT_RETAIN,
+        })
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_the_store() {
+        let store = store("round-trip");
+        let path = store.write(7, &sample()).unwrap();
+        let read: Snapshot = SnapshotStore::read_file(&path).unwrap();
+        assert_eq!(read, sample());
+        assert_eq!(store.latest::<Snapshot>(), Some(sample()));
+    }
+
+    #[test]
+    fn a_corrupted_payload_fails_its_checksum() {
+        let path = store("corrupt").write(7, &sample()).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let read = SnapshotStore::read_file::<Snapshot>(&path);
+        assert!(matches!(read, Err(SnapshotError::ChecksumMismatch)));
+    }
+
+    // bincode lays a struct out as its fields in order, so a tuple of the fields an older
+    // layout had encodes exactly what that version wrote
+    #[test]
+    fn v2_payloads_decode_without_later_state() {
+        let sample = sample();
+        let payload = bincode::serialize(&(
+            &sample.orderbooks,
+            &sample.balances,
+            &sample.order_ids,
+            sample.journal_seq,
+        ))
+        .unwrap();
+
+        let snapshot = Snapshot::decode(2, &payload).unwrap();
+        assert_eq!(snapshot.orderbooks, sample.orderbooks);
+        assert_eq!(snapshot.balances, sample.balances);
+        assert_eq!(snapshot.order_ids, sample.order_ids);
+        assert_eq!(snapshot.journal_seq, 7);
+        assert!(snapshot.depth_update_ids.is_empty());
+        assert!(snapshot.tickers.is_empty());
+        assert!(snapshot.klines.is_empty());
+        assert!(snapshot.backfill_klines);
+    }
+
+    #[test]
+    fn v3_payloads_keep_depth_update_ids() {
+        let sample = sample();
+        let payload = bincode::serialize(&(
+            &sample.orderbooks,
+            &sample.balances,
+            &sample.order_ids,
+            sample.journal_seq,
+            &sample.depth_update_ids,
+        ))
+        .unwrap();
+
+        let snapshot = Snapshot::decode(3, &payload).unwrap();
+        assert_eq!(snapshot.orderbooks, sample.orderbooks);
+        assert_eq!(snapshot.depth_update_ids, sample.depth_update_ids);
+        assert!(snapshot.tickers.is_empty());
+        assert!(snapshot.klines.is_empty());
+        assert!(snapshot.backfill_klines);
+    }
+
+    #[test]
+    fn v4_payloads_seed_klines_from_the_tickers() {
+        let sample = sample();
+        let payload = bincode::serialize(&(
+            &sample.orderbooks,
+            &sample.balances,
+            &sample.order_ids,
+            sample.journal_seq,
+            &sample.depth_update_ids,
+            &sample.tickers,
+        ))
+        .unwrap();
+
+        let snapshot = Snapshot::decode(4, &payload).unwrap();
+        assert_eq!(snapshot.orderbooks, sample.orderbooks);
+        assert_eq!(snapshot.depth_update_ids, sample.depth_update_ids);
+        assert_eq!(snapshot.tickers, sample.tickers);
+        assert_eq!(snapshot.klines, sample.klines);
+        assert!(snapshot.backfill_klines);
+    }
+
+    #[test]
+    fn legacy_json_and_unknown_versions() {
+        let json = serde_json::to_vec(&sample()).unwrap();
+        let snapshot = Snapshot::decode(LEGACY_JSON_VERSION, &json).unwrap();
+        assert_eq!(snapshot.orderbooks, sample().orderbooks);
+        assert!(snapshot.backfill_klines);
+
+        let payload = sample().encode().unwrap();
+        assert!(matches!(
+            Snapshot::decode(Snapshot::VERSION + 1, &payload),
+            Err(SnapshotError::UnsupportedVersion(6))
+        ));
+    }
 }
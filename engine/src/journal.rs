@@ -0,0 +1,221 @@
+use protocol::from_api::MessageFromApi;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_JOURNAL_PATH: &str = "./journal.log";
+
+/// One accepted command, written as a single JSON line before the engine applies it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub seq: u64,
+    // when the command was accepted, in ms since the epoch; 0 in journals written before
+    // timestamps were recorded
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(deserialize_with = "deserialize_command")]
+    pub command: MessageFromApi,
+}
+
+// journals written before commands carried a `type` tag hold `{"CreateOrder": {"data": ...}}`
+fn deserialize_command<'de, D: Deserializer<'de>>(d: D) -> Result<MessageFromApi, D::Error> {
+    let value = match Value::deserialize(d)? {
+        Value::Object(map) if map.len() == 1 && !map.contains_key("type") => {
+            let (variant, fields) = map.into_iter().next().unwrap();
+            match fields {
+                Value::Object(mut fields) => {
+                    fields.insert("type".to_string(), Value::String(variant));
+                    Value::Object(fields)
+                }
+                fields => Value::Object([(variant, fields)].into_iter().collect()),
+            }
+        }
+        value => value,
+    };
+    serde_json::from_value(value).map_err(serde::de::Error::custom)
+}
+
+/// Append-only, fsynced command log. Sequence numbers start at 1 and never repeat, so a
+/// snapshot tagged with the last applied `seq` plus the entries after it rebuild the engine.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    last_seq: u64,
+    // byte length before the newest entry, for taking it back with `discard`
+    last_start: Option<u64>,
+}
+
+impl Journal {
+    /// Opens or creates the journal. A torn last line left by a crash mid-append is cut off;
+    /// anything unreadable before that is reported as corruption.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let (entries, valid_len) = Self::scan(path)?;
+        if valid_len < file.metadata()?.len() {
+            eprintln!(
+                "Discarding incomplete journal entry at byte {} of {}",
+                valid_len,
+                path.display()
+            );
+            file.set_len(valid_len)?;
+        }
+
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file,
+            last_seq: entries.last().map_or(0, |entry| entry.seq),
+            last_start: None,
+        })
+    }
+
+    // keeps numbering ahead of a snapshot when the journal was removed or rotated
+    pub fn advance_to(&mut self, seq: u64) {
+        self.last_seq = self.last_seq.max(seq);
+    }
+
+    /// Durably records `command`, accepted at `timestamp`, and returns its sequence number.
+    pub fn append(&mut self, command: &MessageFromApi, timestamp: u64) -> io::Result<u64> {
+        let entry = JournalEntry {
+            seq: self.last_seq + 1,
+            timestamp,
+            command: command.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        // a failed write may still leave part of the line behind, which would sit in front of
+        // the next entry and make the journal unreadable
+        let start = self.file.metadata()?.len();
+        if let Err(e) = self
+            .file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data())
+        {
+            if let Err(truncate) = self.file.set_len(start) {
+                eprintln!("Failed to roll back a partial journal entry: {}", truncate);
+            }
+            return Err(e);
+        }
+        self.last_seq = entry.seq;
+        self.last_start = Some(start);
+        Ok(entry.seq)
+    }
+
+    /// Takes back the newest entry, `seq`, e.g. one the engine failed to apply. Its sequence
+    /// number is handed out again by the next `append`.
+    pub fn discard(&mut self, seq: u64) -> io::Result<()> {
+        match self.last_start {
+            Some(start) if seq == self.last_seq => {
+                self.file.set_len(start)?;
+                self.file.sync_data()?;
+                self.last_seq -= 1;
+                self.last_start = None;
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("seq {} is not the newest journal entry", seq),
+            )),
+        }
+    }
+
+    /// Entries with a sequence number above `seq`, in order.
+    pub fn entries_after(&self, seq: u64) -> io::Result<Vec<JournalEntry>> {
+        let (entries, _) = Self::scan(&self.path)?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.seq > seq)
+            .collect())
+    }
+
+    // parses every complete entry and returns them with the byte length they cover
+    fn scan(path: &Path) -> io::Result<(Vec<JournalEntry>, u64)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut entries: Vec<JournalEntry> = Vec::new();
+        let mut valid_len = 0;
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+
+            let entry = match serde_json::from_str::<JournalEntry>(&line) {
+                Ok(entry) if line.ends_with('\n') => entry,
+                // only the final line may be partial
+                _ if reader.fill_buf()?.is_empty() => break,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupt journal entry at byte {}", valid_len),
+                    ))
+                }
+            };
+            if let Some(previous) = entries.last() {
+                if entry.seq != previous.seq + 1 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("journal jumps from seq {} to {}", previous.seq, entry.seq),
+                    ));
+                }
+            }
+
+            valid_len += read as u64;
+            entries.push(entry);
+        }
+
+        Ok((entries, valid_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::decimal::Decimal;
+    use protocol::from_api::OnRamp;
+
+    fn on_ramp(txn_id: &str) -> MessageFromApi {
+        MessageFromApi::OnRamp {
+            data: OnRamp {
+                amount: Decimal::from(100),
+                user_id: "alice".to_string(),
+                txn_id: txn_id.to_string(),
+            },
+        }
+    }
+
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn discarded_entries_leave_the_file_and_free_their_seq() {
+        let path = journal_path("discard");
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.append(&on_ramp("t1"), 1).unwrap(), 1);
+        assert_eq!(journal.append(&on_ramp("t2"), 2).unwrap(), 2);
+
+        assert!(journal.discard(1).is_err());
+        journal.discard(2).unwrap();
+        assert!(journal.discard(1).is_err());
+        assert_eq!(journal.append(&on_ramp("t3"), 3).unwrap(), 2);
+
+        let entries = Journal::open(&path).unwrap().entries_after(0).unwrap();
+        let accepted: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.seq, entry.timestamp))
+            .collect();
+        assert_eq!(accepted, vec![(1, 1), (2, 3)]);
+    }
+}
@@ -0,0 +1,112 @@
+use protocol::decimal::{Decimal, Precision};
+use protocol::kline::KlineInterval;
+use protocol::to_ws::KlineData;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// candles kept per interval and market, older ones are dropped
+pub const RETENTION: usize = 1000;
+
+/// Trades aggregated over one span of time.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Candle {
+    pub open_time: u64, // ms since the epoch
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub quote_volume: Decimal,
+    pub trades: u64,
+}
+
+impl Candle {
+    pub fn new(open_time: u64, price: Decimal, quantity: Decimal) -> Self {
+        Candle {
+            open_time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity,
+            quote_volume: price.checked_mul(quantity).unwrap_or(Decimal::MAX),
+            trades: 1,
+        }
+    }
+
+    /// Folds in a later candle. Volumes stop at the largest `Decimal` rather than wrap.
+    pub fn absorb(&mut self, later: &Candle) {
+        self.high = self.high.max(later.high);
+        self.low = self.low.min(later.low);
+        self.close = later.close;
+        self.volume = self.volume.saturating_add(later.volume);
+        self.quote_volume = self.quote_volume.saturating_add(later.quote_volume);
+        self.trades = self.trades.saturating_add(later.trades);
+    }
+
+    pub fn kline(&self, symbol: &str, interval: KlineInterval, precision: Precision) -> KlineData {
+        KlineData {
+            e: "kline".to_string(),
+            s: symbol.to_string(),
+            i: interval,
+            open_time: self.open_time,
+            close_time: self.open_time + interval.millis() - 1,
+            o: precision.price(self.open),
+            h: precision.price(self.high),
+            l: precision.price(self.low),
+            c: precision.price(self.close),
+            v: precision.quantity(self.volume),
+            v_2: precision.quote(self.quote_volume),
+            n: self.trades,
+        }
+    }
+}
+
+/// Candles of one market at every `KlineInterval`, oldest first.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Klines {
+    candles: BTreeMap<KlineInterval, VecDeque<Candle>>,
+}
+
+impl Klines {
+    /// Adds a trade executed at `timestamp` (ms since the epoch).
+    pub fn record(&mut self, timestamp: u64, price: Decimal, quantity: Decimal) {
+        self.merge(&Candle::new(timestamp, price, quantity));
+    }
+
+    /// Adds trades already aggregated into a candle no wider than a minute, e.g. when
+    /// backfilling from the ticker.
+    pub fn merge(&mut self, candle: &Candle) {
+        for interval in KlineInterval::ALL {
+            let open_time = interval.open_time(candle.open_time);
+            let candles = self.candles.entry(interval).or_default();
+            match candles.back_mut() {
+                Some(last) if last.open_time >= open_time => last.absorb(candle),
+                _ => {
+                    candles.push_back(Candle {
+                        open_time,
+                        ..candle.clone()
+                    });
+                    if candles.len() > RETENTION {
+                        candles.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// The candle trades currently go into.
+    pub fn latest(&self, interval: KlineInterval) -> Option<&Candle> {
+        self.candles.get(&interval)?.back()
+    }
+
+    /// Candles opening between `start` and `end` inclusive.
+    pub fn range(&self, interval: KlineInterval, start: u64, end: u64) -> Vec<&Candle> {
+        self.candles
+            .get(&interval)
+            .into_iter()
+            .flatten()
+            .filter(|candle| (start..=end).contains(&candle.open_time))
+            .collect()
+    }
+}
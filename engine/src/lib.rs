@@ -0,0 +1,11 @@
+pub mod bus;
+pub mod engine;
+pub mod journal;
+pub mod klines;
+pub mod orderbook;
+pub mod redis_manager;
+pub mod snapshot;
+pub mod ticker;
+pub mod utils;
+
+use orderbook::*;
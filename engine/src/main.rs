@@ -1,82 +1,22 @@
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
-use rand::Rng;
 use std::sync::{Arc, Mutex};
 
-mod orderbook;
-mod redis_manager;
-mod typs;
-mod utils;
-mod engine;
-
-use orderbook::*;
-use utils::*;
-
-const BASE_ASSET: &str = "BTC";
-const QUOTE_ASSET: &str = "USD";
-pub const GLOBAL_TRADE_ID: usize = 0;
-
-#[post("/api/v1/order")]
-async fn post_order(
-    req: web::Json<OrderInputSchema>,
-    bookwithqty: web::Data<Arc<Mutex<BookWithQuantity>>>,
-    orderbook: web::Data<Arc<Mutex<OrderBook>>>,
-) -> impl Responder {
-    let order_data = req.into_inner();
-    let base_asset = order_data.base_asset;
-    let quote_asset = order_data.quote_asset;
-    let price = order_data.price;
-    let mut quantity = order_data.quantity;
-    let kind = order_data.side;
-
-    let order_id = get_order_id();
-
-    if base_asset != BASE_ASSET || quote_asset != QUOTE_ASSET {
-        return HttpResponse::BadRequest().body("Invalid assets");
+use engine::engine::{Engine, Snapshot};
+
+fn main() -> std::io::Result<()> {
+    // `engine export-snapshot [file]` dumps a snapshot as JSON instead of starting the engine
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export-snapshot") {
+        let path = args.get(1).map(std::path::Path::new);
+        return match Snapshot::export_json(path) {
+            Ok(json) => {
+                println!("{}", json);
+                Ok(())
+            }
+            Err(e) => Err(std::io::Error::other(e.to_string())),
+        };
     }
 
-    let fill_result = fill_order(order_id, price, &mut quantity, kind, bookwithqty, orderbook);
-
-    // Prepare the response
-    HttpResponse::Ok().json(serde_json::json!({
-        "orderId": order_id,
-        "executedQty": fill_result.executedqty,
-        "fills": fill_result.fills
-    }))
-}
-
-fn get_order_id() -> usize {
-    let mut rng = rand::thread_rng();
-    rng.gen::<u64>() as usize // Generate a random u64 and cast it to usize
-}
-
-#[get("/")]
-async fn hello() -> impl Responder {
-    HttpResponse::Ok().body("hello world")
-}
-
-#[post("/echo")]
-async fn echo(req_body: String) -> impl Responder {
-    HttpResponse::Ok().body(req_body)
-}
-
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    // Instantiate and share the BookWithQuantity and OrderBook instances
-    let bookwithquantity = Arc::new(Mutex::new(BookWithQuantity::new()));
-    let orderbook = Arc::new(Mutex::new(OrderBook {
-        bids: Vec::new(),
-        asks: Vec::new(),
-    }));
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(bookwithquantity.clone()))
-            .app_data(web::Data::new(orderbook.clone()))
-            .service(hello)
-            .service(echo)
-            .service(web::scope("").service(post_order))
-    })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    // orders reach the engine only through the Redis command list the api gateway feeds
+    Engine::run(Arc::new(Mutex::new(Engine::new())));
+    Ok(())
 }
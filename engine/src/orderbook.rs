@@ -1,35 +1,8 @@
+use protocol::decimal::Decimal;
+use protocol::markets::MarketConfig;
+use protocol::order::{Depth, Kind, Order, OrderOutcome, SelfTradePrevention, TimeInForce};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub enum Status {
-    Accepted,
-    Rejected,
-}
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
-pub enum Kind {
-    BUY,
-    SELL,
-}
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub enum Market {
-    TataInr,
-    GoogleDollar,
-    NvidiaInr,
-    TeslaDollar,
-}
-impl Market {
-    pub fn assets(&self) -> (&str, &str) {
-        match self {
-            Market::TataInr => ("TATA", "INR"),
-            Market::GoogleDollar => ("GOOGLE", "DOLLAR"),
-            Market::NvidiaInr => ("NVIDIA", "INR"),
-            Market::TeslaDollar => ("TESLA", "DOLLAR"),
-        }
-    }
-}
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
 #[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
 pub enum FillStatus {
@@ -38,142 +11,382 @@ pub enum FillStatus {
     Filled,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub struct Order {
-    pub order_id: String,
-    pub price: usize,
-    pub quantity: usize,
-    pub filled: usize,
-    pub side: Kind,
-    user_id: String,
-}
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub struct Bid {
-    pub order: Order,
-    pub side: Kind,
-}
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub struct Ask {
-    pub order: Order,
-    pub side: Kind,
-}
-
+/// A resting order reduced or removed by self-trade prevention.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-pub struct Depth {
-    pub bid_depth: HashMap<usize, usize>,
-    pub ask_depth: HashMap<usize, usize>,
+pub struct SelfTradeCancel {
+    pub order: Order,
+    pub cancelled_qty: Decimal,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Fillresult {
     pub _status: FillStatus,
-    pub executedqty: usize,
+    pub outcome: OrderOutcome,
+    pub executedqty: Decimal,
     pub fills: Vec<Fills>,
-    depth: Depth,
+    pub self_trade_qty: Decimal, // incoming quantity dropped by self-trade prevention
+    pub self_trade_cancels: Vec<SelfTradeCancel>,
+}
+
+/// Orders resting at a single price, oldest first.
+pub type PriceLevel = VecDeque<Order>;
+
+/// What a market order would take from the opposite side of the book.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Sweep {
+    pub quantity: Decimal,
+    pub quote_quantity: Decimal,
+    pub worst_price: Decimal,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct OrderBook {
-    pub bids: Vec<Bid>,
-    pub asks: Vec<Ask>,
-    pub base_asset: String,
-    pub quote_asset: String,
-    pub last_trade_id: usize,
-    pub current_price: usize,
-    pub bid_depth: HashMap<usize, usize>, // Price to total quantity for bids
-    pub ask_depth: HashMap<usize, usize>,
+    pub bids: BTreeMap<Decimal, PriceLevel>, // best bid is the last key
+    pub asks: BTreeMap<Decimal, PriceLevel>, // best ask is the first key
+    pub orders: HashMap<String, (Kind, Decimal)>, // order_id to side and price level
+    pub market: MarketConfig,
+    pub last_trade_id: usize, // strictly increasing, one per fill
+    pub current_price: Decimal,
+    pub bid_depth: BTreeMap<Decimal, Decimal>, // Price to total quantity for bids
+    pub ask_depth: BTreeMap<Decimal, Decimal>,
 }
 
 impl OrderBook {
-    pub fn new(
-        base_asset: String,
-        bids: Vec<Bid>,
-        asks: Vec<Ask>,
-        last_trade_id: usize,
-        current_price: usize,
-    ) -> Self {
+    pub fn new(market: MarketConfig, last_trade_id: usize, current_price: Decimal) -> Self {
         OrderBook {
-            bids,
-            asks,
-            base_asset,
-            quote_asset: String::from("INR"),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            orders: HashMap::new(),
+            market,
             last_trade_id,
             current_price,
-            bid_depth: HashMap::new(),
-            ask_depth: HashMap::new(),
+            bid_depth: BTreeMap::new(),
+            ask_depth: BTreeMap::new(),
         }
     }
 
-    pub fn ticker(&self) -> String {
-        format!("{}_{}", &self.base_asset, &self.quote_asset)
+    pub fn ticker(&self) -> &str {
+        &self.market.symbol
     }
 
     pub fn getsnapshot(&self) -> Self {
         self.clone()
     }
 
-    pub fn add_order(&mut self, order: &mut Order) -> Fillresult {
-        let fill_result = match order.side {
-            Kind::BUY => self.match_bid(order.clone()),
-            Kind::SELL => self.match_ask(order.clone()),
+    /// Matches `order` and rests what is left according to `time_in_force`. `budget` is what
+    /// was locked for the order, in the quote asset for buys and the base asset for sells;
+    /// matching stops before the taker would spend more than that.
+    pub fn add_order(
+        &mut self,
+        order: &mut Order,
+        time_in_force: TimeInForce,
+        self_trade_prevention: SelfTradePrevention,
+        budget: Option<Decimal>,
+    ) -> Fillresult {
+        match time_in_force {
+            TimeInForce::Fok => {
+                if self.fillable(order, self_trade_prevention) < order.quantity {
+                    return self.rejected(OrderOutcome::Killed);
+                }
+            }
+            TimeInForce::PostOnly | TimeInForce::PostOnlySlide => {
+                if let Some(touch) = self.crossed_touch(order) {
+                    if time_in_force == TimeInForce::PostOnly {
+                        return self.rejected(OrderOutcome::PostOnlyRejected);
+                    }
+                    let tick = self.market.rules.tick_size;
+                    let repriced = match order.side {
+                        Kind::BUY => touch.checked_sub(tick).filter(|p| !p.is_zero()),
+                        Kind::SELL => touch.checked_add(tick),
+                    };
+                    let Some(price) = repriced else {
+                        return self.rejected(OrderOutcome::PostOnlyRejected);
+                    };
+                    order.price = price;
+                    self.rest(order);
+                    return self.rejected(OrderOutcome::Repriced);
+                }
+            }
+            TimeInForce::Gtc | TimeInForce::Ioc => {}
+        }
+
+        let mut fill_result = match order.side {
+            Kind::BUY => self.match_bid(order.clone(), self_trade_prevention, budget),
+            Kind::SELL => self.match_ask(order.clone(), self_trade_prevention, budget),
         };
 
         order.filled = fill_result.executedqty;
+        order.quantity -= fill_result.self_trade_qty;
 
-        if fill_result.executedqty < order.quantity {
-            match order.side {
-                Kind::BUY => self.bids.push(Bid {
-                    order: order.clone(),
-                    side: Kind::BUY,
-                }),
-                Kind::SELL => self.asks.push(Ask {
-                    order: order.clone(),
-                    side: Kind::SELL,
-                }),
-            }
+        // IOC and FOK remainders are cancelled, post-only orders that get here did not cross
+        let rests = matches!(
+            time_in_force,
+            TimeInForce::Gtc | TimeInForce::PostOnly | TimeInForce::PostOnlySlide
+        );
+        if rests && fill_result.executedqty < order.quantity {
+            self.rest(order);
+            fill_result.outcome = OrderOutcome::Resting;
         }
 
         fill_result
     }
 
-    pub fn match_bid(&mut self, order: Order) -> Fillresult {
+    fn rest(&mut self, order: &Order) {
+        let levels = match order.side {
+            Kind::BUY => &mut self.bids,
+            Kind::SELL => &mut self.asks,
+        };
+        levels
+            .entry(order.price)
+            .or_default()
+            .push_back(order.clone());
+        self.orders
+            .insert(order.order_id.clone(), (order.side, order.price));
+        self.add_depth(order.side, order.price, order.quantity - order.filled);
+    }
+
+    // best opposite price if the order would trade on arrival
+    fn crossed_touch(&self, order: &Order) -> Option<Decimal> {
+        match order.side {
+            Kind::BUY => self
+                .asks
+                .keys()
+                .next()
+                .copied()
+                .filter(|&best_ask| order.price >= best_ask),
+            Kind::SELL => self
+                .bids
+                .keys()
+                .next_back()
+                .copied()
+                .filter(|&best_bid| order.price <= best_bid),
+        }
+    }
+
+    fn rejected(&self, outcome: OrderOutcome) -> Fillresult {
+        Fillresult {
+            _status: FillStatus::Unfilled,
+            outcome,
+            executedqty: Decimal::ZERO,
+            fills: vec![],
+            self_trade_qty: Decimal::ZERO,
+            self_trade_cancels: vec![],
+        }
+    }
+
+    // quantity matching would execute right now, with the owner's resting orders cancelling
+    // against the order under `stp` the same way they do in `match_bid`/`match_ask`
+    fn fillable(&self, order: &Order, stp: SelfTradePrevention) -> Decimal {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &PriceLevel)>> = match order.side {
+            Kind::BUY => Box::new(self.asks.range(..=order.price)),
+            Kind::SELL => Box::new(self.bids.range(order.price..).rev()),
+        };
+
+        let mut executed_qty = Decimal::ZERO;
+        let mut self_trade_qty = Decimal::ZERO;
+        for maker in levels.flat_map(|(_, level)| level) {
+            let remaining = order.quantity - executed_qty - self_trade_qty;
+            if remaining.is_zero() {
+                break;
+            }
+            let maker_remaining = maker.quantity - maker.filled;
+            if maker.user_id == order.user_id {
+                if let Some((_, taker_cancelled)) = stp.cancels(remaining, maker_remaining) {
+                    self_trade_qty += taker_cancelled;
+                    continue;
+                }
+            }
+            executed_qty += std::cmp::min(remaining, maker_remaining);
+        }
+        executed_qty
+    }
+
+    /// Worst price a market order may trade at, from an explicit protection price and/or a
+    /// maximum slippage in basis points away from the best opposite price.
+    pub fn protection_price(
+        &self,
+        side: Kind,
+        protection_price: Option<Decimal>,
+        max_slippage_bps: Option<u64>,
+    ) -> Option<Decimal> {
+        let slippage_price = max_slippage_bps.and_then(|bps| match side {
+            Kind::BUY => {
+                let best_ask = *self.asks.keys().next()?;
+                best_ask.checked_add(best_ask.checked_mul_ratio(bps, 10_000)?)
+            }
+            Kind::SELL => {
+                let best_bid = *self.bids.keys().next_back()?;
+                Some(best_bid.saturating_sub(best_bid.checked_mul_ratio(bps, 10_000)?))
+            }
+        });
+
+        match (protection_price, slippage_price) {
+            (Some(a), Some(b)) => Some(match side {
+                Kind::BUY => std::cmp::min(a, b),
+                Kind::SELL => std::cmp::max(a, b),
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Walks the opposite side without touching it. `quote_quantity` switches the order to
+    /// spending (or receiving) that much of the quote asset instead of a base `quantity`.
+    /// Orders of `skip_user` are left out, as self-trade prevention keeps a taker from
+    /// trading with them.
+    pub fn sweep(
+        &self,
+        side: Kind,
+        skip_user: Option<&str>,
+        quantity: Decimal,
+        quote_quantity: Option<Decimal>,
+        limit: Option<Decimal>,
+    ) -> Sweep {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &PriceLevel)>> = match side {
+            Kind::BUY => Box::new(self.asks.iter()),
+            Kind::SELL => Box::new(self.bids.iter().rev()),
+        };
+
+        let mut sweep = Sweep::default();
+        for (&price, level) in levels {
+            let within_limit = match (side, limit) {
+                (_, None) => true,
+                (Kind::BUY, Some(limit)) => price <= limit,
+                (Kind::SELL, Some(limit)) => price >= limit,
+            };
+            if !within_limit {
+                break;
+            }
+
+            let available: Decimal = level
+                .iter()
+                .filter(|o| skip_user != Some(o.user_id.as_str()))
+                .map(|o| o.quantity - o.filled)
+                .fold(Decimal::ZERO, Decimal::saturating_add);
+            let wanted = match quote_quantity {
+                // whole lots only, so the spend never exceeds the quote amount
+                Some(quote) => (quote - sweep.quote_quantity)
+                    .checked_div(price)
+                    .map(|qty| qty.floor_to(self.market.rules.lot_size))
+                    .unwrap_or(Decimal::ZERO),
+                None => quantity - sweep.quantity,
+            };
+            if wanted.is_zero() {
+                break;
+            }
+            let take = std::cmp::min(available, wanted);
+            if take.is_zero() {
+                continue;
+            }
+            let Some(quote_quantity) = take
+                .checked_mul(price)
+                .and_then(|cost| sweep.quote_quantity.checked_add(cost))
+            else {
+                break;
+            };
+
+            sweep.quantity += take;
+            sweep.quote_quantity = quote_quantity;
+            sweep.worst_price = price;
+        }
+
+        sweep
+    }
+
+    pub fn match_bid(
+        &mut self,
+        order: Order,
+        stp: SelfTradePrevention,
+        budget: Option<Decimal>,
+    ) -> Fillresult {
         let mut fills: Vec<Fills> = Vec::new();
-        let mut executed_qty: usize = 0;
-        let mut to_remove = Vec::new();
-
-        for (i, ask) in self.asks.iter_mut().enumerate() {
-            if ask.order.price <= order.price && executed_qty < order.quantity {
-                let filled_qty = std::cmp::min(
-                    order.quantity - executed_qty,
-                    ask.order.quantity - ask.order.filled,
-                );
+        let mut executed_qty = Decimal::ZERO;
+        let mut self_trade_qty = Decimal::ZERO;
+        let mut self_trade_cancels = Vec::new();
+        let mut spent = Decimal::ZERO;
+        let mut out_of_budget = false;
+
+        while executed_qty + self_trade_qty < order.quantity {
+            let Some(mut level) = self.asks.first_entry() else {
+                break;
+            };
+            let price = *level.key();
+            if price > order.price {
+                break;
+            }
+
+            let queue = level.get_mut();
+            let mut level_taken = Decimal::ZERO;
+            while executed_qty + self_trade_qty < order.quantity {
+                let Some(ask) = queue.front_mut() else {
+                    break;
+                };
+                let remaining = order.quantity - executed_qty - self_trade_qty;
+
+                if ask.user_id == order.user_id {
+                    if let Some((maker_cancelled, taker_cancelled)) =
+                        stp.cancels(remaining, ask.quantity - ask.filled)
+                    {
+                        self_trade_qty += taker_cancelled;
+                        if !maker_cancelled.is_zero() {
+                            ask.quantity -= maker_cancelled;
+                            level_taken += maker_cancelled;
+                            self_trade_cancels.push(SelfTradeCancel {
+                                order: ask.clone(),
+                                cancelled_qty: maker_cancelled,
+                            });
+                            if ask.filled == ask.quantity {
+                                let ask_id = ask.order_id.clone();
+                                queue.pop_front();
+                                self.orders.remove(&ask_id);
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                let mut filled_qty = std::cmp::min(remaining, ask.quantity - ask.filled);
+                if let Some(budget) = budget {
+                    // whole lots the locked quote asset still pays for at this price
+                    let affordable = (budget - spent)
+                        .checked_div(price)
+                        .map(|qty| qty.floor_to(self.market.rules.lot_size));
+                    filled_qty =
+                        affordable.map_or(filled_qty, |qty| std::cmp::min(filled_qty, qty));
+                }
+                if filled_qty.is_zero() {
+                    out_of_budget = true;
+                    break;
+                }
+                if budget.is_some() {
+                    spent += price * filled_qty;
+                }
                 executed_qty += filled_qty;
-                ask.order.filled += filled_qty;
+                level_taken += filled_qty;
+                ask.filled += filled_qty;
 
+                self.last_trade_id += 1;
                 fills.push(Fills {
-                    price: ask.order.price,
+                    price,
                     quantity: filled_qty,
-                    tradeid: self.last_trade_id + 1,
-                    other_userid: ask.order.user_id.clone(),
-                    marker_userid: order.order_id.clone(),
+                    tradeid: self.last_trade_id,
+                    other_userid: ask.user_id.clone(),
+                    marker_userid: ask.order_id.clone(),
                 });
 
-                if ask.order.filled == ask.order.quantity {
-                    to_remove.push(i);
-                }
-
-                // Update ask depth
-                *self.ask_depth.entry(ask.order.price).or_insert(0) -= filled_qty;
-                if self.ask_depth[&ask.order.price] == 0 {
-                    self.ask_depth.remove(&ask.order.price);
+                if ask.filled == ask.quantity {
+                    let ask_id = ask.order_id.clone();
+                    queue.pop_front();
+                    self.orders.remove(&ask_id);
                 }
             }
-        }
 
-        for &i in to_remove.iter().rev() {
-            self.asks.remove(i);
+            if queue.is_empty() {
+                level.remove();
+            }
+            self.reduce_depth(Kind::SELL, price, level_taken);
+            if out_of_budget {
+                break;
+            }
         }
 
         Fillresult {
@@ -181,54 +394,109 @@ impl OrderBook {
             executedqty: executed_qty,
             _status: if executed_qty == order.quantity {
                 FillStatus::Filled
-            } else if executed_qty > 0 {
+            } else if !executed_qty.is_zero() {
                 FillStatus::PartiallyFilled
             } else {
                 FillStatus::Unfilled
             },
-            depth: Depth {
-                bid_depth: (self.bid_depth.clone()),
-                ask_depth: (self.ask_depth.clone()),
+            outcome: if executed_qty == order.quantity {
+                OrderOutcome::Filled
+            } else if !self_trade_qty.is_zero() && executed_qty + self_trade_qty == order.quantity {
+                OrderOutcome::SelfTradePrevented
+            } else {
+                OrderOutcome::RemainderCancelled
             },
+            self_trade_qty,
+            self_trade_cancels,
         }
     }
 
-    pub fn match_ask(&mut self, order: Order) -> Fillresult {
+    pub fn match_ask(
+        &mut self,
+        order: Order,
+        stp: SelfTradePrevention,
+        budget: Option<Decimal>,
+    ) -> Fillresult {
         let mut fills: Vec<Fills> = Vec::new();
-        let mut executed_qty: usize = 0;
-        let mut to_remove = Vec::new();
-
-        for (i, bid) in self.bids.iter_mut().enumerate() {
-            if bid.order.price >= order.price && executed_qty < order.quantity {
-                let filled_qty = std::cmp::min(
-                    order.quantity - executed_qty,
-                    bid.order.quantity - bid.order.filled,
-                );
+        let mut executed_qty = Decimal::ZERO;
+        let mut self_trade_qty = Decimal::ZERO;
+        let mut self_trade_cancels = Vec::new();
+        let mut out_of_budget = false;
+
+        while executed_qty + self_trade_qty < order.quantity {
+            let Some(mut level) = self.bids.last_entry() else {
+                break;
+            };
+            let price = *level.key();
+            if price < order.price {
+                break;
+            }
+
+            let queue = level.get_mut();
+            let mut level_taken = Decimal::ZERO;
+            while executed_qty + self_trade_qty < order.quantity {
+                let Some(bid) = queue.front_mut() else {
+                    break;
+                };
+                let remaining = order.quantity - executed_qty - self_trade_qty;
+
+                if bid.user_id == order.user_id {
+                    if let Some((maker_cancelled, taker_cancelled)) =
+                        stp.cancels(remaining, bid.quantity - bid.filled)
+                    {
+                        self_trade_qty += taker_cancelled;
+                        if !maker_cancelled.is_zero() {
+                            bid.quantity -= maker_cancelled;
+                            level_taken += maker_cancelled;
+                            self_trade_cancels.push(SelfTradeCancel {
+                                order: bid.clone(),
+                                cancelled_qty: maker_cancelled,
+                            });
+                            if bid.filled == bid.quantity {
+                                let bid_id = bid.order_id.clone();
+                                queue.pop_front();
+                                self.orders.remove(&bid_id);
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                let mut filled_qty = std::cmp::min(remaining, bid.quantity - bid.filled);
+                if let Some(budget) = budget {
+                    filled_qty = std::cmp::min(filled_qty, budget.saturating_sub(executed_qty));
+                }
+                if filled_qty.is_zero() {
+                    out_of_budget = true;
+                    break;
+                }
                 executed_qty += filled_qty;
-                bid.order.filled += filled_qty;
+                level_taken += filled_qty;
+                bid.filled += filled_qty;
 
+                self.last_trade_id += 1;
                 fills.push(Fills {
-                    price: bid.order.price,
+                    price,
                     quantity: filled_qty,
-                    tradeid: self.last_trade_id + 1,
-                    other_userid: bid.order.user_id.clone(),
-                    marker_userid: order.order_id.clone(),
+                    tradeid: self.last_trade_id,
+                    other_userid: bid.user_id.clone(),
+                    marker_userid: bid.order_id.clone(),
                 });
 
-                if bid.order.filled == bid.order.quantity {
-                    to_remove.push(i);
-                }
-
-                // Update bid depth
-                *self.bid_depth.entry(bid.order.price).or_insert(0) -= filled_qty;
-                if self.bid_depth[&bid.order.price] == 0 {
-                    self.bid_depth.remove(&bid.order.price);
+                if bid.filled == bid.quantity {
+                    let bid_id = bid.order_id.clone();
+                    queue.pop_front();
+                    self.orders.remove(&bid_id);
                 }
             }
-        }
 
-        for &i in to_remove.iter().rev() {
-            self.bids.remove(i);
+            if queue.is_empty() {
+                level.remove();
+            }
+            self.reduce_depth(Kind::BUY, price, level_taken);
+            if out_of_budget {
+                break;
+            }
         }
 
         Fillresult {
@@ -236,94 +504,147 @@ impl OrderBook {
             executedqty: executed_qty,
             _status: if executed_qty == order.quantity {
                 FillStatus::Filled
-            } else if executed_qty > 0 {
+            } else if !executed_qty.is_zero() {
                 FillStatus::PartiallyFilled
             } else {
                 FillStatus::Unfilled
             },
-            depth: Depth {
-                bid_depth: (self.bid_depth.clone()),
-                ask_depth: (self.ask_depth.clone()),
+            outcome: if executed_qty == order.quantity {
+                OrderOutcome::Filled
+            } else if !self_trade_qty.is_zero() && executed_qty + self_trade_qty == order.quantity {
+                OrderOutcome::SelfTradePrevented
+            } else {
+                OrderOutcome::RemainderCancelled
             },
+            self_trade_qty,
+            self_trade_cancels,
         }
     }
 
-    pub fn get_open_orders(&self, user_id: &str) -> Vec<Order> {
-        let asks: Vec<Order> = self
-            .asks
-            .iter()
-            .filter(|x| x.order.user_id == user_id)
-            .map(|x| x.order.clone())
-            .collect();
+    /// Aggregated quantity for the best `levels` prices on each side.
+    pub fn get_depth(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self
+                .bid_depth
+                .iter()
+                .rev()
+                .take(levels)
+                .map(|(&price, &qty)| (price, qty))
+                .collect(),
+            asks: self
+                .ask_depth
+                .iter()
+                .take(levels)
+                .map(|(&price, &qty)| (price, qty))
+                .collect(),
+        }
+    }
 
-        let bids: Vec<Order> = self
-            .bids
-            .iter()
-            .filter(|x| x.order.user_id == user_id)
-            .map(|x| x.order.clone())
-            .collect();
+    /// Total quantity resting at `price` on one side, zero for an empty level.
+    pub fn depth_at(&self, side: Kind, price: Decimal) -> Decimal {
+        let depth = match side {
+            Kind::BUY => &self.bid_depth,
+            Kind::SELL => &self.ask_depth,
+        };
+        depth.get(&price).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// Whether `qty` more can rest on `side` without any level's depth overflowing. Checked
+    /// before an order is accepted so the depth totals below always stay exact.
+    pub fn has_depth_room(&self, side: Kind, qty: Decimal) -> bool {
+        let depth = match side {
+            Kind::BUY => &self.bid_depth,
+            Kind::SELL => &self.ask_depth,
+        };
+        depth
+            .values()
+            .try_fold(qty, |total, &level| total.checked_add(level))
+            .is_some()
+    }
 
-        [asks, bids].concat()
+    fn add_depth(&mut self, side: Kind, price: Decimal, qty: Decimal) {
+        let depth = match side {
+            Kind::BUY => &mut self.bid_depth,
+            Kind::SELL => &mut self.ask_depth,
+        };
+        let total = depth.entry(price).or_default();
+        *total = total
+            .checked_add(qty)
+            .expect("depth room is checked before an order rests");
     }
 
-    pub fn cancel_bid(&mut self, order: &Order) -> Option<usize> {
-        if let Some(index) = self
-            .bids
-            .iter()
-            .position(|x| x.order.order_id == order.order_id)
-        {
-            let price = self.bids[index].order.price;
-            self.bids.remove(index);
-            Some(price)
-        } else {
-            None
+    fn reduce_depth(&mut self, side: Kind, price: Decimal, qty: Decimal) {
+        let depth = match side {
+            Kind::BUY => &mut self.bid_depth,
+            Kind::SELL => &mut self.ask_depth,
+        };
+        if let Some(total) = depth.get_mut(&price) {
+            *total = total
+                .checked_sub(qty)
+                .expect("depth only shrinks by quantity that rested");
+            if total.is_zero() {
+                depth.remove(&price);
+            }
         }
     }
 
-    pub fn cancel_ask(&mut self, order: &Order) -> Option<usize> {
-        if let Some(index) = self
-            .asks
+    pub fn get_order(&self, order_id: &str) -> Option<&Order> {
+        let (side, price) = self.orders.get(order_id)?;
+        let levels = match side {
+            Kind::BUY => &self.bids,
+            Kind::SELL => &self.asks,
+        };
+        levels
+            .get(price)?
             .iter()
-            .position(|x| x.order.order_id == order.order_id)
-        {
-            let price = self.asks[index].order.price;
-            self.asks.remove(index);
-            Some(price)
-        } else {
-            None
-        }
+            .find(|order| order.order_id == order_id)
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, PartialOrd)]
-pub struct OrderInputSchema {
-    pub base_asset: String,
-    pub quote_asset: String,
-    pub price: usize,
-    pub quantity: usize,
-    pub side: Kind,
-    // pub typ: OrderType,
-}
+    pub fn get_open_orders(&self, user_id: &str) -> Vec<Order> {
+        self.asks
+            .values()
+            .chain(self.bids.values())
+            .flatten()
+            .filter(|order| order.user_id == user_id)
+            .cloned()
+            .collect()
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct BookWithQuantity {
-    pub bids: HashMap<usize, usize>,
-    pub asks: HashMap<usize, usize>,
-}
+    pub fn cancel_bid(&mut self, order: &Order) -> Option<Decimal> {
+        self.remove_resting(Kind::BUY, &order.order_id)
+    }
 
-impl BookWithQuantity {
-    pub fn new() -> BookWithQuantity {
-        BookWithQuantity {
-            bids: HashMap::new(),
-            asks: HashMap::new(),
+    pub fn cancel_ask(&mut self, order: &Order) -> Option<Decimal> {
+        self.remove_resting(Kind::SELL, &order.order_id)
+    }
+
+    fn remove_resting(&mut self, side: Kind, order_id: &str) -> Option<Decimal> {
+        let &(resting_side, price) = self.orders.get(order_id)?;
+        if resting_side != side {
+            return None;
+        }
+
+        let levels = match side {
+            Kind::BUY => &mut self.bids,
+            Kind::SELL => &mut self.asks,
+        };
+        let level = levels.get_mut(&price)?;
+        let index = level.iter().position(|x| x.order_id == order_id)?;
+        let order = level.remove(index)?;
+        if level.is_empty() {
+            levels.remove(&price);
         }
+        self.orders.remove(order_id);
+        self.reduce_depth(side, price, order.quantity - order.filled);
+
+        Some(price)
     }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
 pub struct Fills {
-    pub price: usize,
-    pub quantity: usize,
+    pub price: Decimal,
+    pub quantity: Decimal,
     pub tradeid: usize,
     pub other_userid: String,
     pub marker_userid: String,
@@ -334,3 +655,554 @@ pub enum Status {
     Accepted,
     Rejected,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::decimal::Precision;
+    use protocol::markets::{MarketStatus, TradingRules};
+
+    fn d(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    // TATA_INR as shipped in markets.toml
+    fn tata_inr() -> OrderBook {
+        let market = MarketConfig {
+            symbol: "TATA_INR".to_string(),
+            base_asset: "TATA".to_string(),
+            quote_asset: "INR".to_string(),
+            precision: Precision {
+                price_decimals: 2,
+                quantity_decimals: 0,
+            },
+            rules: TradingRules {
+                tick_size: d("0.05"),
+                lot_size: d("1"),
+                min_quantity: d("1"),
+                max_quantity: d("100000"),
+                min_notional: d("100"),
+            },
+            status: MarketStatus::Trading,
+        };
+        OrderBook::new(market, 0, Decimal::ZERO)
+    }
+
+    fn order(id: &str, side: Kind, user_id: &str, price: &str, quantity: &str) -> Order {
+        Order {
+            order_id: id.to_string(),
+            price: d(price),
+            quantity: d(quantity),
+            filled: Decimal::ZERO,
+            side,
+            user_id: user_id.to_string(),
+        }
+    }
+
+    fn submit(
+        book: &mut OrderBook,
+        order: &mut Order,
+        time_in_force: TimeInForce,
+        stp: SelfTradePrevention,
+    ) -> Fillresult {
+        book.add_order(order, time_in_force, stp, None)
+    }
+
+    // rests a GTC order that is not expected to cross
+    fn rest(book: &mut OrderBook, id: &str, side: Kind, user_id: &str, price: &str, qty: &str) {
+        let mut order = order(id, side, user_id, price, qty);
+        let result = submit(
+            book,
+            &mut order,
+            TimeInForce::Gtc,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Resting);
+    }
+
+    fn fills(result: &Fillresult) -> Vec<(&str, Decimal, Decimal)> {
+        result
+            .fills
+            .iter()
+            .map(|fill| (fill.marker_userid.as_str(), fill.price, fill.quantity))
+            .collect()
+    }
+
+    fn ids(level: &PriceLevel) -> Vec<&str> {
+        level.iter().map(|order| order.order_id.as_str()).collect()
+    }
+
+    #[test]
+    fn resting_orders_queue_by_price_then_arrival() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::BUY, "alice", "99", "5");
+        rest(&mut book, "2", Kind::BUY, "bob", "98", "5");
+        rest(&mut book, "3", Kind::BUY, "carol", "99", "2");
+        rest(&mut book, "4", Kind::SELL, "dave", "101", "4");
+
+        assert_eq!(
+            book.bids.keys().copied().collect::<Vec<_>>(),
+            vec![d("98"), d("99")]
+        );
+        assert_eq!(ids(&book.bids[&d("99")]), vec!["1", "3"]);
+        assert_eq!(ids(&book.asks[&d("101")]), vec!["4"]);
+        assert_eq!(book.get_order("3").unwrap().user_id, "carol");
+        assert_eq!(book.get_order("4").unwrap().side, Kind::SELL);
+        assert!(book.get_order("5").is_none());
+        assert_eq!(book.get_open_orders("alice").len(), 1);
+    }
+
+    #[test]
+    fn matches_best_price_first_then_oldest_first() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "5");
+        rest(&mut book, "3", Kind::SELL, "carol", "99", "5");
+
+        let mut buy = order("4", Kind::BUY, "dave", "100", "8");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Gtc,
+            SelfTradePrevention::None,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::Filled);
+        assert_eq!(
+            fills(&result),
+            vec![("3", d("99"), d("5")), ("1", d("100"), d("3"))]
+        );
+        assert_eq!(
+            result.fills.iter().map(|f| f.tradeid).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(book.get_order("1").unwrap().filled, d("3"));
+        assert_eq!(book.get_order("2").unwrap().filled, Decimal::ZERO);
+        assert!(book.get_order("3").is_none());
+    }
+
+    #[test]
+    fn gtc_remainder_rests_at_its_limit() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "4");
+
+        let mut buy = order("2", Kind::BUY, "bob", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Gtc,
+            SelfTradePrevention::None,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::Resting);
+        assert_eq!(result.executedqty, d("4"));
+        assert_eq!(book.get_order("2").unwrap().filled, d("4"));
+        assert_eq!(book.depth_at(Kind::BUY, d("100")), d("6"));
+        assert_eq!(book.depth_at(Kind::SELL, d("100")), Decimal::ZERO);
+    }
+
+    #[test]
+    fn ioc_cancels_its_remainder() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "4");
+
+        let mut buy = order("2", Kind::BUY, "bob", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Ioc,
+            SelfTradePrevention::None,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::RemainderCancelled);
+        assert_eq!(result.executedqty, d("4"));
+        assert!(book.get_order("2").is_none());
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn fok_is_killed_unless_it_fills_completely() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "99", "3");
+        rest(&mut book, "2", Kind::SELL, "alice", "100", "3");
+
+        let mut short = order("3", Kind::BUY, "bob", "100", "7");
+        let result = submit(
+            &mut book,
+            &mut short,
+            TimeInForce::Fok,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Killed);
+        assert!(result.fills.is_empty());
+        assert_eq!(book.depth_at(Kind::SELL, d("99")), d("3"));
+
+        let mut full = order("4", Kind::BUY, "bob", "100", "6");
+        let result = submit(
+            &mut book,
+            &mut full,
+            TimeInForce::Fok,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Filled);
+        assert_eq!(result.executedqty, d("6"));
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn post_only_rests_or_is_rejected_when_it_would_cross() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+
+        let mut crossing = order("2", Kind::BUY, "bob", "100", "5");
+        let result = submit(
+            &mut book,
+            &mut crossing,
+            TimeInForce::PostOnly,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(result.outcome, OrderOutcome::PostOnlyRejected);
+        assert!(book.bids.is_empty());
+
+        let mut passive = order("3", Kind::BUY, "bob", "99.95", "5");
+        let result = submit(
+            &mut book,
+            &mut passive,
+            TimeInForce::PostOnly,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Resting);
+        assert_eq!(book.depth_at(Kind::BUY, d("99.95")), d("5"));
+    }
+
+    #[test]
+    fn post_only_slide_rests_one_tick_behind_the_touch() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+
+        let mut buy = order("2", Kind::BUY, "bob", "101", "5");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::PostOnlySlide,
+            SelfTradePrevention::None,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::Repriced);
+        assert!(result.fills.is_empty());
+        assert_eq!(buy.price, d("99.95"));
+        assert_eq!(book.get_order("2").unwrap().price, d("99.95"));
+    }
+
+    #[test]
+    fn without_prevention_a_user_trades_with_themselves() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+
+        let mut buy = order("2", Kind::BUY, "alice", "100", "5");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Ioc,
+            SelfTradePrevention::None,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::Filled);
+        assert_eq!(fills(&result), vec![("1", d("100"), d("5"))]);
+    }
+
+    // alice's own ask of 5 sits ahead of bob's 5 at the same price
+    fn book_with_own_ask_first() -> OrderBook {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "5");
+        book
+    }
+
+    #[test]
+    fn cancel_newest_drops_the_incoming_order() {
+        let mut book = book_with_own_ask_first();
+
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Gtc,
+            SelfTradePrevention::CancelNewest,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::SelfTradePrevented);
+        assert_eq!(result.executedqty, Decimal::ZERO);
+        assert_eq!(result.self_trade_qty, d("10"));
+        assert!(result.self_trade_cancels.is_empty());
+        assert!(book.bids.is_empty());
+        assert_eq!(book.depth_at(Kind::SELL, d("100")), d("10"));
+    }
+
+    #[test]
+    fn cancel_oldest_drops_the_resting_order_and_keeps_matching() {
+        let mut book = book_with_own_ask_first();
+
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Ioc,
+            SelfTradePrevention::CancelOldest,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::RemainderCancelled);
+        assert_eq!(fills(&result), vec![("2", d("100"), d("5"))]);
+        assert_eq!(result.self_trade_qty, Decimal::ZERO);
+        assert_eq!(result.self_trade_cancels.len(), 1);
+        assert_eq!(result.self_trade_cancels[0].order.order_id, "1");
+        assert_eq!(result.self_trade_cancels[0].cancelled_qty, d("5"));
+        assert!(book.get_order("1").is_none());
+        assert!(book.asks.is_empty());
+        assert!(book.ask_depth.is_empty());
+    }
+
+    #[test]
+    fn cancel_both_drops_both_orders() {
+        let mut book = book_with_own_ask_first();
+
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Gtc,
+            SelfTradePrevention::CancelBoth,
+        );
+
+        assert_eq!(result.outcome, OrderOutcome::SelfTradePrevented);
+        assert_eq!(result.executedqty, Decimal::ZERO);
+        assert_eq!(result.self_trade_qty, d("10"));
+        assert_eq!(result.self_trade_cancels[0].cancelled_qty, d("5"));
+        assert!(book.get_order("1").is_none());
+        assert!(book.bids.is_empty());
+        assert_eq!(book.depth_at(Kind::SELL, d("100")), d("5"));
+    }
+
+    #[test]
+    fn decrement_shrinks_both_orders_by_the_overlap() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "3");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "5");
+
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Gtc,
+            SelfTradePrevention::Decrement,
+        );
+
+        assert_eq!(fills(&result), vec![("2", d("100"), d("5"))]);
+        assert_eq!(result.self_trade_qty, d("3"));
+        assert_eq!(result.self_trade_cancels[0].cancelled_qty, d("3"));
+        // 10 less the 3 decremented, 5 of it filled and the other 2 resting
+        assert_eq!(result.outcome, OrderOutcome::Resting);
+        assert_eq!(buy.quantity, d("7"));
+        assert_eq!(book.depth_at(Kind::BUY, d("100")), d("2"));
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn fok_counts_only_what_self_trade_prevention_lets_it_fill() {
+        // cancelling alice's own ask leaves only bob's 5 for her 10
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "99", "5");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "5");
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Fok,
+            SelfTradePrevention::CancelOldest,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Killed);
+        assert!(book.get_order("1").is_some());
+        assert!(book.bids.is_empty());
+
+        // cancel newest stops at alice's own ask although bob could fill all of it
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "99", "5");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "10");
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Fok,
+            SelfTradePrevention::CancelNewest,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Killed);
+        assert!(book.bids.is_empty());
+
+        let mut buy = order("4", Kind::BUY, "alice", "100", "10");
+        let result = submit(
+            &mut book,
+            &mut buy,
+            TimeInForce::Fok,
+            SelfTradePrevention::CancelOldest,
+        );
+        assert_eq!(result.outcome, OrderOutcome::Filled);
+        assert_eq!(fills(&result), vec![("2", d("100"), d("10"))]);
+        assert!(book.get_order("1").is_none());
+    }
+
+    #[test]
+    fn market_sweeps_leave_out_orders_self_trade_prevention_skips() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "99", "5");
+        rest(&mut book, "2", Kind::SELL, "bob", "100", "10");
+
+        let everything = book.sweep(Kind::BUY, None, d("10"), None, None);
+        assert_eq!(everything.quote_quantity, d("995"));
+
+        let others = book.sweep(Kind::BUY, Some("alice"), d("10"), None, None);
+        assert_eq!(others.quantity, d("10"));
+        assert_eq!(others.quote_quantity, d("1000"));
+        assert_eq!(others.worst_price, d("100"));
+
+        // what a market buy locks from the sweep covers what matching then spends
+        let mut buy = order("3", Kind::BUY, "alice", "100", "10");
+        let result = book.add_order(
+            &mut buy,
+            TimeInForce::Ioc,
+            SelfTradePrevention::CancelOldest,
+            Some(others.quote_quantity),
+        );
+        assert_eq!(result.outcome, OrderOutcome::Filled);
+        assert_eq!(fills(&result), vec![("2", d("100"), d("10"))]);
+    }
+
+    #[test]
+    fn quote_sweeps_buy_whole_units_within_the_amount() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "2");
+        rest(&mut book, "2", Kind::SELL, "alice", "150", "10");
+
+        let sweep = book.sweep(Kind::BUY, None, Decimal::ZERO, Some(d("500")), None);
+        assert_eq!(sweep.quantity, d("4"));
+        assert_eq!(sweep.quote_quantity, d("500"));
+        assert_eq!(sweep.worst_price, d("150"));
+
+        let limited = book.sweep(Kind::BUY, None, d("10"), None, Some(d("120")));
+        assert_eq!(limited.quantity, d("2"));
+        assert_eq!(limited.worst_price, d("100"));
+    }
+
+    #[test]
+    fn matching_stops_at_the_locked_budget() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "10");
+        let mut buy = order("2", Kind::BUY, "bob", "100", "10");
+        let result = book.add_order(
+            &mut buy,
+            TimeInForce::Ioc,
+            SelfTradePrevention::None,
+            Some(d("350")),
+        );
+        assert_eq!(result.outcome, OrderOutcome::RemainderCancelled);
+        assert_eq!(result.executedqty, d("3"));
+        assert_eq!(book.depth_at(Kind::SELL, d("100")), d("7"));
+
+        rest(&mut book, "3", Kind::BUY, "carol", "90", "10");
+        let mut sell = order("4", Kind::SELL, "dave", "90", "10");
+        let result = book.add_order(
+            &mut sell,
+            TimeInForce::Ioc,
+            SelfTradePrevention::None,
+            Some(d("4")),
+        );
+        assert_eq!(result.executedqty, d("4"));
+        assert_eq!(book.depth_at(Kind::BUY, d("90")), d("6"));
+    }
+
+    #[test]
+    fn protection_price_takes_the_tighter_of_price_and_slippage() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::SELL, "alice", "100", "5");
+        rest(&mut book, "2", Kind::BUY, "bob", "90", "5");
+
+        assert_eq!(
+            book.protection_price(Kind::BUY, None, Some(250)),
+            Some(d("102.5"))
+        );
+        assert_eq!(
+            book.protection_price(Kind::BUY, Some(d("101")), Some(250)),
+            Some(d("101"))
+        );
+        assert_eq!(
+            book.protection_price(Kind::SELL, Some(d("80")), Some(500)),
+            Some(d("85.5"))
+        );
+        assert_eq!(book.protection_price(Kind::SELL, None, None), None);
+    }
+
+    #[test]
+    fn cancelling_removes_the_order_and_its_depth() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::BUY, "alice", "99", "5");
+        rest(&mut book, "2", Kind::BUY, "bob", "99", "3");
+        let first = book.get_order("1").unwrap().clone();
+
+        assert_eq!(book.cancel_ask(&first), None);
+        assert_eq!(book.cancel_bid(&first), Some(d("99")));
+        assert!(book.get_order("1").is_none());
+        assert_eq!(book.depth_at(Kind::BUY, d("99")), d("3"));
+        assert_eq!(book.cancel_bid(&first), None);
+
+        let second = book.get_order("2").unwrap().clone();
+        book.cancel_bid(&second);
+        assert!(book.bids.is_empty());
+        assert!(book.bid_depth.is_empty());
+        assert!(book.orders.is_empty());
+    }
+
+    #[test]
+    fn depth_tracks_resting_quantity_per_level() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::BUY, "alice", "98", "5");
+        rest(&mut book, "2", Kind::BUY, "alice", "99", "5");
+        rest(&mut book, "3", Kind::BUY, "bob", "99", "2");
+        rest(&mut book, "4", Kind::SELL, "carol", "101", "4");
+
+        let depth = book.get_depth(10);
+        assert_eq!(depth.bids, vec![(d("99"), d("7")), (d("98"), d("5"))]);
+        assert_eq!(depth.asks, vec![(d("101"), d("4"))]);
+        assert_eq!(book.get_depth(1).bids, vec![(d("99"), d("7"))]);
+
+        let mut sell = order("5", Kind::SELL, "dave", "99", "6");
+        submit(
+            &mut book,
+            &mut sell,
+            TimeInForce::Gtc,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(book.depth_at(Kind::BUY, d("99")), d("1"));
+
+        let mut sell = order("6", Kind::SELL, "dave", "99", "1");
+        submit(
+            &mut book,
+            &mut sell,
+            TimeInForce::Gtc,
+            SelfTradePrevention::None,
+        );
+        assert_eq!(book.depth_at(Kind::BUY, d("99")), Decimal::ZERO);
+        assert!(!book.bid_depth.contains_key(&d("99")));
+        assert_eq!(book.get_depth(10).bids, vec![(d("98"), d("5"))]);
+    }
+
+    #[test]
+    fn depth_room_counts_everything_resting_on_the_side() {
+        let mut book = tata_inr();
+        rest(&mut book, "1", Kind::BUY, "alice", "98", "5");
+        rest(&mut book, "2", Kind::BUY, "bob", "99", "2");
+
+        let room = Decimal::MAX - d("7");
+        assert!(book.has_depth_room(Kind::BUY, room));
+        assert!(!book.has_depth_room(Kind::BUY, room + d("1")));
+        assert!(book.has_depth_room(Kind::SELL, Decimal::MAX));
+    }
+}
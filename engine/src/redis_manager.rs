@@ -1,77 +1,240 @@
-use crate::{Kind, Market};
-use serde::{Deserialize, Serialize};
-const URL: &str = "rediss://127.0.0.1/";
-use crate::typs::to_api::MessageToApi;
-use crate::typs::to_ws::WsMessage;
-use redis::Commands;
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum DbMessage {
-    TradeAdded { data: TradeAdded },
-    OrderUpdate { data: OrderUpdate },
-}
+use crate::bus::{BusError, MessageBus};
+use protocol::db::DbEntry;
+use protocol::to_api::MessageToApi;
+use protocol::to_ws::WsMessage;
+use protocol::transport::{RedisConfig, DB_QUEUE, MESSAGES_QUEUE};
+use redis::{Commands, Connection, RedisResult};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// reconnect attempts per read, waiting INITIAL_BACKOFF, then twice as long each time
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+// messages waiting for the publisher before `enqueue` refuses more
+const OUTGOING_CAPACITY: usize = 100_000;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TradeAdded {
-    pub id: String,
-    pub is_buyer_maker: bool,
-    pub price: usize,
-    pub quantity: usize,
-    pub quotequantity: usize,
-    pub timestamp: usize,
-    pub market: Market,
+/// Redis-backed `MessageBus`. Each direction keeps one long-lived connection, reopened with
+/// exponential backoff when it drops. Outgoing messages are queued for a background thread,
+/// so the engine never waits on Redis while it holds its lock.
+pub struct RedisManager {
+    client: redis::Client,
+    outgoing: SyncSender<Outgoing>,
+    // why a db update was lost, reported through `status`
+    db_failure: Arc<Mutex<Option<String>>>,
+    // dedicated to blocking reads so they never hold up outgoing messages
+    consumer: Mutex<Option<Connection>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct OrderUpdate {
-    pub order_id: String,
-    pub executed_qty: usize,
-    pub market: Option<Market>,
-    pub price: Option<usize>,
-    pub quantity: Option<usize>,
-    pub side: Option<Kind>,
+// a message waiting for the publisher thread
+enum Outgoing {
+    Push { list: &'static str, payload: String },
+    Publish { channel: String, payload: String },
 }
 
-pub struct RedisManager {
-    client: redis::Client,
+impl Outgoing {
+    fn send(&self, conn: &mut Connection) -> RedisResult<()> {
+        match self {
+            Outgoing::Push { list, payload } => conn.lpush(*list, payload),
+            Outgoing::Publish { channel, payload } => conn.publish(channel, payload),
+        }
+    }
 }
 
 impl RedisManager {
-    pub fn new() -> Result<Self, redis::RedisError> {
-        let client = redis::Client::open(URL)?;
-        Ok(RedisManager { client })
+    pub fn new() -> RedisResult<Self> {
+        Self::with_config(&RedisConfig::from_env())
     }
 
-    pub fn connect(&self) -> Result<redis::Connection, redis::RedisError> {
-        self.client.get_connection()
+    /// Fails only on an invalid URL; connections are opened on first use.
+    pub fn with_config(config: &RedisConfig) -> RedisResult<Self> {
+        Self::with_capacity(config, OUTGOING_CAPACITY)
     }
 
-    pub fn push_message(&self, msg: &DbMessage) -> Result<(), redis::RedisError> {
-        let mut conn = self.connect().unwrap();
-        let serialized_message = serde_json::to_string(&msg).unwrap();
-        let _ = conn.lpush("db_processor", serialized_message);
-        Ok(())
+    fn with_capacity(config: &RedisConfig, capacity: usize) -> RedisResult<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let (outgoing, queue) = mpsc::sync_channel(capacity);
+        let db_failure = Arc::new(Mutex::new(None));
+        let publisher = client.clone();
+        let publisher_failure = db_failure.clone();
+        thread::spawn(move || send_queued(publisher, queue, publisher_failure));
+        Ok(RedisManager {
+            client,
+            outgoing,
+            db_failure,
+            consumer: Mutex::new(None),
+        })
     }
 
-    pub fn publish_message(
-        &self,
-        channel: String,
-        msg: &WsMessage,
-    ) -> Result<(), redis::RedisError> {
-        let mut conn = self.connect().unwrap();
-        let serialized_message = serde_json::to_string(&msg).unwrap();
-        conn.publish(channel, serialized_message)?;
-        Ok(())
+    // never blocks: a publisher that cannot keep up fails the send instead of stalling the
+    // engine, and a db update lost that way is remembered for `status`
+    fn enqueue(&self, message: Outgoing) -> Result<(), BusError> {
+        let is_push = matches!(message, Outgoing::Push { .. });
+        let error = match self.outgoing.try_send(message) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(_)) => BusError::QueueFull,
+            Err(TrySendError::Disconnected(_)) => BusError::PublisherStopped,
+        };
+        if is_push {
+            record_failure(&self.db_failure, error.to_string());
+        }
+        Err(error)
     }
 
-    pub fn send_to_api(
+    // Runs a read on the consumer connection, connecting first if needed. Connection
+    // failures drop the connection and retry after a backoff; other errors are returned as is.
+    fn with_consumer<T>(
         &self,
-        client_id: String,
-        msg: &MessageToApi,
-    ) -> Result<(), redis::RedisError> {
-        let mut conn = self.connect().unwrap();
-        let serialized_message = serde_json::to_string(&msg).unwrap();
-        conn.publish(client_id, serialized_message)?;
-        Ok(())
+        mut command: impl FnMut(&mut Connection) -> RedisResult<T>,
+    ) -> RedisResult<T> {
+        let mut slot = self.consumer.lock().unwrap();
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            let result = match slot.as_mut() {
+                Some(conn) => command(conn),
+                None => self
+                    .client
+                    .get_connection_with_timeout(CONNECT_TIMEOUT)
+                    .and_then(|conn| command(slot.insert(conn))),
+            };
+
+            match result {
+                Err(e) if is_connection_error(&e) => {
+                    *slot = None;
+                    if attempt == MAX_RETRIES {
+                        return Err(e);
+                    }
+                    eprintln!("Redis connection lost ({}), retrying in {:?}", e, backoff);
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Sends queued messages in order, reconnecting for as long as Redis stays away while later
+// messages wait in the queue. A message lost with its connection is sent again; for an
+// LPUSH that Redis may already have applied, the db processor drops the copy by its id.
+fn send_queued(
+    client: redis::Client,
+    queue: Receiver<Outgoing>,
+    db_failure: Arc<Mutex<Option<String>>>,
+) {
+    let mut slot: Option<Connection> = None;
+    for message in queue {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let conn = match slot.as_mut() {
+                Some(conn) => conn,
+                None => match client.get_connection_with_timeout(CONNECT_TIMEOUT) {
+                    Ok(conn) => slot.insert(conn),
+                    Err(e) => {
+                        eprintln!("Redis unreachable ({}), retrying in {:?}", e, backoff);
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        continue;
+                    }
+                },
+            };
+
+            match message.send(conn) {
+                Ok(()) => break,
+                Err(e) if is_connection_error(&e) => {
+                    slot = None;
+                    eprintln!("Redis connection lost ({}), retrying in {:?}", e, backoff);
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => {
+                    eprintln!("Failed to send to Redis: {}", e);
+                    if let Outgoing::Push { list, .. } = &message {
+                        record_failure(&db_failure, format!("push to {}: {}", list, e));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// keeps the first failure, later ones are usually its consequence
+fn record_failure(db_failure: &Mutex<Option<String>>, reason: String) {
+    db_failure.lock().unwrap().get_or_insert(reason);
+}
+
+fn is_connection_error(e: &redis::RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout()
+}
+
+impl MessageBus for RedisManager {
+    fn push_to_db(&self, entry: &DbEntry) -> Result<(), BusError> {
+        self.enqueue(Outgoing::Push {
+            list: DB_QUEUE,
+            payload: serde_json::to_string(&entry)?,
+        })
+    }
+
+    fn publish_to_ws(&self, channel: &str, msg: &WsMessage) -> Result<(), BusError> {
+        self.enqueue(Outgoing::Publish {
+            channel: channel.to_string(),
+            payload: serde_json::to_string(&msg)?,
+        })
+    }
+
+    fn send_to_api(&self, client_id: &str, msg: &MessageToApi) -> Result<(), BusError> {
+        self.enqueue(Outgoing::Publish {
+            channel: client_id.to_string(),
+            payload: serde_json::to_string(&msg)?,
+        })
+    }
+
+    fn receive_command(&self, timeout: Duration) -> Result<Option<String>, BusError> {
+        let popped: Option<(String, String)> =
+            self.with_consumer(|conn| conn.brpop(MESSAGES_QUEUE, timeout.as_secs_f64()))?;
+        Ok(popped.map(|(_, payload)| payload))
+    }
+
+    fn status(&self) -> Result<(), BusError> {
+        match &*self.db_failure.lock().unwrap() {
+            Some(reason) => Err(BusError::DbUpdateLost(reason.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(n: usize) -> Outgoing {
+        Outgoing::Push {
+            list: DB_QUEUE,
+            payload: n.to_string(),
+        }
+    }
+
+    #[test]
+    fn a_full_queue_refuses_messages_and_reports_the_lost_db_update() {
+        // nothing listens on port 1, so the publisher keeps retrying its first message
+        let config = RedisConfig {
+            url: "redis://127.0.0.1:1".to_string(),
+        };
+        let manager = RedisManager::with_capacity(&config, 1).unwrap();
+        assert!(manager.status().is_ok());
+
+        let refused = (0..3)
+            .map(|n| manager.enqueue(push(n)))
+            .filter(|sent| matches!(sent, Err(BusError::QueueFull)))
+            .count();
+        assert!(refused >= 1);
+        assert!(matches!(manager.status(), Err(BusError::DbUpdateLost(_))));
     }
 }
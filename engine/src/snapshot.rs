@@ -0,0 +1,240 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_SNAPSHOT_DIR: &str = "./snapshots";
+pub const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 3;
+pub const DEFAULT_SNAPSHOT_RETAIN: usize = 5;
+
+const PREFIX: &str = "snapshot-";
+const EXTENSION: &str = "snap";
+// snapshots written before the binary format: bare JSON, implicitly version 1
+const LEGACY_EXTENSION: &str = "json";
+pub const LEGACY_JSON_VERSION: u32 = 1;
+
+// magic, format version, payload length, crc32 of the payload
+const MAGIC: &[u8; 8] = b"ENGSNAP\0";
+const HEADER_LEN: usize = 8 + 4 + 8 + 4;
+
+/// State that `SnapshotStore` can persist. `decode` is handed the version a payload was
+/// written with and migrates older layouts to the current one.
+pub trait SnapshotData: Serialize + DeserializeOwned {
+    const VERSION: u32;
+
+    fn encode(&self) -> Result<Vec<u8>, SnapshotError> {
+        bincode::serialize(self).map_err(|e| SnapshotError::Encode(e.to_string()))
+    }
+
+    fn decode(version: u32, payload: &[u8]) -> Result<Self, SnapshotError>;
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadHeader,
+    ChecksumMismatch,
+    UnsupportedVersion(u32),
+    Encode(String),
+    Decode(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::BadHeader => write!(f, "not a snapshot file or truncated header"),
+            SnapshotError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            SnapshotError::Encode(e) => write!(f, "failed to encode snapshot: {}", e),
+            SnapshotError::Decode(e) => write!(f, "failed to decode snapshot: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    pub retain: usize, // newest snapshots kept on disk, at least one
+}
+
+impl SnapshotConfig {
+    /// Reads `SNAPSHOT_DIR`, `SNAPSHOT_INTERVAL_SECS` and `SNAPSHOT_RETAIN`, falling back to
+    /// the defaults for anything unset or unparsable.
+    pub fn from_env() -> Self {
+        let parse = |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+        SnapshotConfig {
+            dir: env::var("SNAPSHOT_DIR")
+                .unwrap_or_else(|_| DEFAULT_SNAPSHOT_DIR.to_string())
+                .into(),
+            interval: Duration::from_secs(
+                parse("SNAPSHOT_INTERVAL_SECS").unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_SECS),
+            ),
+            retain: parse("SNAPSHOT_RETAIN")
+                .map_or(DEFAULT_SNAPSHOT_RETAIN, |n| n as usize)
+                .max(1),
+        }
+    }
+}
+
+/// Snapshot files named after the journal sequence they cover, newest last.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    config: SnapshotConfig,
+}
+
+impl SnapshotStore {
+    pub fn new(config: SnapshotConfig) -> Self {
+        SnapshotStore { config }
+    }
+
+    pub fn config(&self) -> &SnapshotConfig {
+        &self.config
+    }
+
+    /// Newest snapshot that can be read, skipping (but keeping) any that fail to load.
+    pub fn latest<T: SnapshotData>(&self) -> Option<T> {
+        let mut paths = self.list().unwrap_or_else(|e| {
+            eprintln!("Failed to list snapshots: {}", e);
+            vec![]
+        });
+        paths.reverse();
+
+        paths
+            .into_iter()
+            .find_map(|path| match Self::read_file(&path) {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    eprintln!("Skipping unreadable snapshot {}: {}", path.display(), e);
+                    None
+                }
+            })
+    }
+
+    /// Loads one snapshot file, verifying its header and checksum.
+    pub fn read_file<T: SnapshotData>(path: &Path) -> Result<T, SnapshotError> {
+        let bytes = fs::read(path)?;
+        if path.extension().and_then(|ext| ext.to_str()) == Some(LEGACY_EXTENSION) {
+            return T::decode(LEGACY_JSON_VERSION, &bytes);
+        }
+
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(SnapshotError::BadHeader);
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        let len = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+        let checksum = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+        let payload = &bytes[HEADER_LEN..];
+        if payload.len() as u64 != len {
+            return Err(SnapshotError::BadHeader);
+        }
+        if crc32fast::hash(payload) != checksum {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+
+        T::decode(version, payload)
+    }
+
+    /// Writes to a temp file, fsyncs it and renames it into place, so readers only ever see
+    /// complete snapshots. Older snapshots beyond the retention count are then removed.
+    pub fn write<T: SnapshotData>(
+        &self,
+        journal_seq: u64,
+        snapshot: &T,
+    ) -> Result<PathBuf, SnapshotError> {
+        fs::create_dir_all(&self.config.dir)?;
+        let path = self
+            .config
+            .dir
+            .join(format!("{}{:020}.{}", PREFIX, journal_seq, EXTENSION));
+        let tmp = path.with_extension("tmp");
+
+        let payload = snapshot.encode()?;
+        let mut file = File::create(&tmp)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&T::VERSION.to_le_bytes())?;
+        file.write_all(&(payload.len() as u64).to_le_bytes())?;
+        file.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        File::open(&self.config.dir)?.sync_all()?;
+
+        self.prune()?;
+        Ok(path)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let paths = self.list()?;
+        let excess = paths.len().saturating_sub(self.config.retain);
+        for path in &paths[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // completed snapshots of either format, oldest first
+    fn list(&self) -> io::Result<Vec<PathBuf>> {
+        if !self.config.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut paths: Vec<(u64, PathBuf)> = fs::read_dir(&self.config.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter_map(|path| snapshot_seq(&path).map(|seq| (seq, path)))
+            .collect();
+        paths.sort();
+        Ok(paths.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+fn snapshot_seq(path: &Path) -> Option<u64> {
+    let extension = path.extension()?.to_str()?;
+    if extension != EXTENSION && extension != LEGACY_EXTENSION {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(PREFIX)?
+        .parse()
+        .ok()
+}
+
+/// Encodes and writes snapshots on a background thread so the engine only pays for the
+/// clone of its state.
+#[derive(Clone)]
+pub struct SnapshotWriter<T> {
+    sender: Sender<(u64, T)>,
+}
+
+impl<T: SnapshotData + Send + 'static> SnapshotWriter<T> {
+    pub fn spawn(store: SnapshotStore) -> Self {
+        let (sender, receiver) = mpsc::channel::<(u64, T)>();
+        thread::spawn(move || {
+            for (journal_seq, snapshot) in receiver {
+                if let Err(e) = store.write(journal_seq, &snapshot) {
+                    eprintln!("Failed to write snapshot at seq {}: {}", journal_seq, e);
+                }
+            }
+        });
+        SnapshotWriter { sender }
+    }
+
+    pub fn send(&self, journal_seq: u64, snapshot: T) {
+        if self.sender.send((journal_seq, snapshot)).is_err() {
+            eprintln!("Snapshot writer has stopped");
+        }
+    }
+}
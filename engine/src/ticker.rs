@@ -0,0 +1,95 @@
+use crate::klines::Candle;
+use protocol::decimal::{Decimal, Precision};
+use protocol::to_ws::TickerData;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+pub const WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+// trades are summed per minute, so the window rolls forward a minute at a time
+const BUCKET_MS: u64 = 60 * 1000;
+
+/// Rolling 24 hour statistics of one market, kept as per-minute buckets of its trades so
+/// the state stays bounded however busy the market is.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Ticker {
+    buckets: VecDeque<Candle>, // oldest first
+    last_trade_id: usize,
+}
+
+impl Ticker {
+    /// Adds a trade executed at `timestamp` (ms since the epoch) and forgets buckets that
+    /// fell out of the window.
+    pub fn record(&mut self, timestamp: u64, trade_id: usize, price: Decimal, quantity: Decimal) {
+        let start = timestamp - timestamp % BUCKET_MS;
+        let trade = Candle::new(start, price, quantity);
+        match self.buckets.back_mut() {
+            // a clock stepping backwards still lands in the newest bucket
+            Some(bucket) if bucket.open_time >= start => bucket.absorb(&trade),
+            _ => self.buckets.push_back(trade),
+        }
+        self.last_trade_id = trade_id;
+
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.open_time + WINDOW_MS <= timestamp)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// The per-minute buckets still held, oldest first.
+    pub fn buckets(&self) -> impl Iterator<Item = &Candle> {
+        self.buckets.iter()
+    }
+
+    /// The statistics as of `now`. Prices are absent while the window holds no trades.
+    pub fn data(&self, symbol: &str, precision: Precision, now: u64) -> TickerData {
+        let mut window = self
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.open_time + WINDOW_MS > now);
+        let mut data = TickerData {
+            o: None,
+            c: None,
+            h: None,
+            l: None,
+            v: Some(precision.quantity(Decimal::ZERO)),
+            v_2: Some(precision.quote(Decimal::ZERO)),
+            price_change_percent: None,
+            n: 0,
+            s: Some(symbol.to_string()),
+            id: self.last_trade_id,
+            e: "ticker".to_string(),
+        };
+        let Some(first) = window.next() else {
+            return data;
+        };
+
+        let mut total = first.clone();
+        for bucket in window {
+            total.absorb(bucket);
+        }
+        data.o = Some(precision.price(total.open));
+        data.c = Some(precision.price(total.close));
+        data.h = Some(precision.price(total.high));
+        data.l = Some(precision.price(total.low));
+        data.v = Some(precision.quantity(total.volume));
+        data.v_2 = Some(precision.quote(total.quote_volume));
+        data.price_change_percent = Some(percent_change(total.open, total.close));
+        data.n = total.trades;
+        data
+    }
+}
+
+// signed change from `open` to `close` in percent with two decimals, e.g. "-1.25"
+fn percent_change(open: Decimal, close: Decimal) -> String {
+    if open.is_zero() {
+        return "0.00".to_string();
+    }
+    let (open, close) = (open.units() as i128, close.units() as i128);
+    let hundredths = (close - open) * 10_000 / open;
+    let sign = if hundredths < 0 { "-" } else { "" };
+    let hundredths = hundredths.abs();
+    format!("{}{}.{:02}", sign, hundredths / 100, hundredths % 100)
+}
@@ -1,3 +0,0 @@
-pub mod to_api;
-pub mod from_api;
-pub mod to_ws;
\ No newline at end of file
@@ -1,187 +1,23 @@
-use crate::{orderbook::*, GLOBAL_TRADE_ID};
-use actix_web::web;
-use std::sync::{Arc, Mutex};
-
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub enum Status {
-    Accepted,
-    Rejected,
-}
-
-#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
-pub struct Fills {
-    pub price: usize,
-    pub quantity: usize,
-    pub tradeid: usize,
-    pub other_userid: String,
-    pub marker_userid: String
+/// Strictly increasing id source, persisted with the engine snapshot so ids keep
+/// growing across restarts.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Sequence {
+    last: u64,
 }
 
-
-
-pub fn fill_order(
-    order_id: usize,
-    price: usize,
-    quantity: &mut usize,
-    kind: Kind,
-    bookwithquantity: web::Data<Arc<Mutex<BookWithQuantity>>>,
-    orderbook: web::Data<Arc<Mutex<OrderBook>>>,
-) -> Fillresult {
-    let mut fills: Vec<Fills> = Vec::new();
-
-    let max_fill_quantity = get_fill_amount(price, *quantity, kind, &orderbook);
-
-    let mut executedqty = 0;
-
-    if max_fill_quantity < *quantity {
-        Fillresult {
-            _status: Status::Rejected,
-            executedqty: max_fill_quantity,
-            fills: vec![],
-        }
-    } else {
-        let mut book = orderbook.lock().unwrap();
-        let mut bookwithquantity = bookwithquantity.lock().unwrap();
-
-        let mut to_remove = Vec::new();
-        let mut to_update = Vec::new();
-
-        match kind {
-            Kind::BUY => {
-                for o in book.asks.iter_mut() {
-                    if o.order.price <= price {
-                        let filledqty = std::cmp::min(*quantity, o.order.quantity);
-                        o.order.quantity -= filledqty;
-
-                        let bookedqty = bookwithquantity.asks[&price];
-                        to_update.push((price, bookedqty - filledqty));
-
-                        fills.push(Fills {
-                            price: o.order.price,
-                            quantity: filledqty,
-                            tradeid: GLOBAL_TRADE_ID + 1,
-                        });
-                        executedqty += filledqty;
-                        *quantity -= filledqty;
-
-                        if o.order.quantity == 0 {
-                            to_remove.push(o.order.price);
-                        }
-                    }
-                }
-
-                for price in to_remove {
-                    let index = book.index_of_ask(price).expect("ask not found");
-                    book.asks.remove(index);
-                }
-
-                for (price, qty) in to_update {
-                    if qty == 0 {
-                        bookwithquantity.asks.remove(&price);
-                    } else {
-                        bookwithquantity.asks.insert(price, qty);
-                    }
-                }
-
-                if *quantity != 0 {
-                    book.bids.push(Bid {
-                        order: Order {
-                            price,
-                            quantity: *quantity,
-                            order_id,
-                        },
-                        side: Kind::BUY,
-                    });
-
-                    *bookwithquantity.bids.entry(price).or_insert(0) += *quantity;
-                }
-            }
-
-            Kind::SELL => {
-                for o in book.bids.iter_mut() {
-                    if o.order.price >= price {
-                        let filledqty = std::cmp::min(*quantity, o.order.quantity);
-                        o.order.quantity -= filledqty;
-
-                        let bookedqty = bookwithquantity.bids[&price];
-                        to_update.push((price, bookedqty - filledqty));
-
-                        fills.push(Fills {
-                            price: o.order.price,
-                            quantity: filledqty,
-                            tradeid: GLOBAL_TRADE_ID + 1,
-                        });
-                        executedqty += filledqty;
-                        *quantity -= filledqty;
-
-                        if o.order.quantity == 0 {
-                            to_remove.push(o.order.price);
-                        }
-                    }
-                }
-
-                for price in to_remove {
-                    let index = book.index_of_bid(price).expect("bid not found");
-                    book.bids.remove(index);
-                }
-
-                for (price, qty) in to_update {
-                    if qty == 0 {
-                        bookwithquantity.bids.remove(&price);
-                    } else {
-                        bookwithquantity.bids.insert(price, qty);
-                    }
-                }
-
-                if *quantity != 0 {
-                    book.asks.push(Ask {
-                        order: Order {
-                            price,
-                            quantity: *quantity,
-                            order_id,
-                        },
-                        side: Kind::SELL,
-                    });
-                    *bookwithquantity.asks.entry(price).or_insert(0) += *quantity;
-                }
-            }
-        }
-
-        Fillresult {
-            _status: Status::Accepted,
-            executedqty,
-            fills,
-        }
+impl Sequence {
+    pub fn next_id(&mut self) -> u64 {
+        self.last += 1;
+        self.last
     }
 }
 
-pub fn get_fill_amount(
-    price: usize,
-    quantity: usize,
-    kind: Kind,
-    orderbook: &Arc<Mutex<OrderBook>>,
-) -> usize {
-    let mut filled = 0;
-    let book = orderbook.lock().unwrap();
-
-    match kind {
-        Kind::BUY => {
-            for o in book.asks.iter() {
-                if o.order.price < price {
-                    filled = std::cmp::min(quantity, o.order.quantity);
-                }
-            }
-        }
-        Kind::SELL => {
-            for o in book.asks.iter() {
-                if o.order.price > price {
-                    filled = std::cmp::min(quantity, o.order.quantity);
-                }
-            }
-        }
-    }
-
-    filled
+/// Wall clock time in milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
 }
@@ -0,0 +1,656 @@
+use engine::bus::{BusError, InMemoryBus, MessageBus};
+use engine::engine::Engine;
+use protocol::db::DbMessage;
+use protocol::decimal::Decimal;
+use protocol::from_api::{
+    CreateOrder, GetDepth, GetKlines, MessageFromApi, OnRamp, SetMarketStatus,
+};
+use protocol::kline::KlineInterval;
+use protocol::markets::{MarketStatus, RejectReason};
+use protocol::order::{Kind, OrderOutcome, OrderType, SelfTradePrevention, TimeInForce};
+use protocol::to_api::{CancelReason, MessageToApi};
+use protocol::to_ws::WsMessage;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// the engine finds its journal and snapshots through the environment, which tests share
+static ENV: Mutex<()> = Mutex::new(());
+
+fn engine_with_bus(name: &str) -> (Engine, Arc<InMemoryBus>) {
+    engine_with_journal(name, "")
+}
+
+// starts from an empty state directory whose journal holds `journal`
+fn engine_with_journal(name: &str, journal: &str) -> (Engine, Arc<InMemoryBus>) {
+    engine_with_state(name, &[], journal)
+}
+
+// like `engine_with_journal`, with `snapshots` as (file name, contents) in the snapshot dir
+fn engine_with_state(
+    name: &str,
+    snapshots: &[(&str, &str)],
+    journal: &str,
+) -> (Engine, Arc<InMemoryBus>) {
+    let dir = std::env::temp_dir().join(format!("engine-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("snapshots")).unwrap();
+    for (file_name, contents) in snapshots {
+        std::fs::write(dir.join("snapshots").join(file_name), contents).unwrap();
+    }
+    std::fs::write(dir.join("journal.log"), journal).unwrap();
+    std::env::set_var("JOURNAL_PATH", dir.join("journal.log"));
+    std::env::set_var("SNAPSHOT_DIR", dir.join("snapshots"));
+
+    let bus = Arc::new(InMemoryBus::new());
+    (Engine::with_bus(bus.clone()), bus)
+}
+
+// what `Engine::run` does, minus the waiting
+fn drain(engine: &mut Engine, bus: &InMemoryBus) {
+    while let Some(payload) = bus.receive_command(Duration::ZERO).unwrap() {
+        engine.handle_payload(&payload);
+    }
+}
+
+fn limit(side: Kind, user_id: &str, price: &str, quantity: &str) -> MessageFromApi {
+    MessageFromApi::CreateOrder {
+        data: CreateOrder {
+            market: "TATA_INR".to_string(),
+            order_type: Default::default(),
+            time_in_force: Default::default(),
+            self_trade_prevention: Default::default(),
+            price: price.parse().unwrap(),
+            quantity: quantity.parse().unwrap(),
+            quote_quantity: None,
+            protection_price: None,
+            max_slippage_bps: None,
+            side,
+            user_id: user_id.to_string(),
+        },
+    }
+}
+
+fn market(side: Kind, user_id: &str, quantity: &str, stp: SelfTradePrevention) -> MessageFromApi {
+    with(limit(side, user_id, "0", quantity), |order| {
+        order.order_type = OrderType::Market;
+        order.self_trade_prevention = stp;
+    })
+}
+
+// adjusts the order a `limit` or `market` message carries
+fn with(mut message: MessageFromApi, change: impl FnOnce(&mut CreateOrder)) -> MessageFromApi {
+    if let MessageFromApi::CreateOrder { data } = &mut message {
+        change(data);
+    }
+    message
+}
+
+fn depth() -> MessageFromApi {
+    MessageFromApi::GetDepth {
+        data: GetDepth {
+            market: "TATA_INR".to_string(),
+        },
+    }
+}
+
+fn on_ramp(user_id: &str, amount: &str) -> MessageFromApi {
+    MessageFromApi::OnRamp {
+        data: OnRamp {
+            amount: amount.parse().unwrap(),
+            user_id: user_id.to_string(),
+            txn_id: format!("{}-{}", user_id, amount),
+        },
+    }
+}
+
+#[test]
+fn matching_orders_reply_and_publish_through_the_bus() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("match");
+
+    bus.push_command("c1", limit(Kind::SELL, "default_user", "100", "10"));
+    bus.push_command("c2", on_ramp("alice", "5000"));
+    bus.push_command("c3", limit(Kind::BUY, "alice", "100", "10"));
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    let on_ramped = replies.iter().find(|(client_id, _)| client_id == "c2");
+    match on_ramped {
+        Some((_, MessageToApi::Balances { payload })) => {
+            assert_eq!(payload.balances["INR"].available, "5000");
+        }
+        other => panic!("unexpected on-ramp reply {:?}", other),
+    }
+
+    let (_, last) = replies
+        .iter()
+        .rev()
+        .find(|(client_id, _)| client_id == "c3")
+        .expect("no reply to the buy order");
+    match last {
+        MessageToApi::OrderPlaced { payload } => {
+            assert_eq!(payload.executed_qty, "10");
+            assert_eq!(payload.fills.len(), 1);
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+
+    assert!(bus
+        .take_db_messages()
+        .iter()
+        .any(|msg| matches!(msg, DbMessage::TradeAdded { .. })));
+    assert!(bus
+        .take_ws_messages()
+        .iter()
+        .any(|(channel, _)| channel == "trade@TATA_INR"));
+}
+
+#[test]
+fn fills_publish_trades_and_changed_depth_levels() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("depth");
+
+    bus.push_command("c1", limit(Kind::SELL, "default_user", "100", "10"));
+    bus.push_command("c2", on_ramp("alice", "5000"));
+    drain(&mut engine, &bus);
+    bus.take_ws_messages();
+
+    bus.push_command("c3", limit(Kind::BUY, "alice", "100", "15"));
+    drain(&mut engine, &bus);
+
+    let published = bus.take_ws_messages();
+    // trade, depth, ticker and one open candle per kline interval
+    assert_eq!(published.len(), 8);
+    match &published[0] {
+        (channel, WsMessage::TradeAddedMessage { data }) => {
+            assert_eq!(channel, "trade@TATA_INR");
+            assert_eq!((data.q.as_str(), data.m), ("10", false));
+        }
+        other => panic!("expected a trade, got {:?}", other),
+    }
+    // the ask level was taken out and the remainder rests as a bid at the same price
+    match &published[1] {
+        (channel, WsMessage::DepthUpdateMessage { data }) => {
+            assert_eq!(channel, "depth@TATA_INR");
+            assert_eq!(data.a, Some(vec![("100.00".to_string(), "0".to_string())]));
+            assert_eq!(data.b, Some(vec![("100.00".to_string(), "5".to_string())]));
+            // the resting sell took id 1, one id per changed level after that
+            assert_eq!((data.first_update_id, data.last_update_id), (2, 3));
+        }
+        other => panic!("expected a depth update, got {:?}", other),
+    }
+    match &published[2] {
+        (channel, WsMessage::TickerUpdateMessage { data }) => {
+            assert_eq!(channel, "ticker@TATA_INR");
+            assert_eq!(data.c.as_deref(), Some("100.00"));
+            assert_eq!(data.v.as_deref(), Some("10"));
+            assert_eq!(data.price_change_percent.as_deref(), Some("0.00"));
+            assert_eq!(data.n, 1);
+        }
+        other => panic!("expected a ticker, got {:?}", other),
+    }
+    match &published[3] {
+        (channel, WsMessage::KlineUpdateMessage { data }) => {
+            assert_eq!(channel, "kline@TATA_INR_1m");
+            assert_eq!(
+                (data.o.as_str(), data.v.as_str(), data.n),
+                ("100.00", "10", 1)
+            );
+            assert_eq!(data.close_time - data.open_time, 59_999);
+        }
+        other => panic!("expected a kline, got {:?}", other),
+    }
+
+    bus.push_command("c4", depth());
+    drain(&mut engine, &bus);
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::Depth { payload })) => assert_eq!(payload.last_update_id, 3),
+        other => panic!("unexpected depth reply {:?}", other),
+    }
+}
+
+#[test]
+fn replayed_trades_keep_their_journaled_time() {
+    let _env = ENV.lock().unwrap();
+    let journal = [
+        r#"{"seq":1,"timestamp":1700000065000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100","quantity":"2","side":"SELL","user_id":"default_user"}}}"#,
+        r#"{"seq":2,"timestamp":1700000065000,"command":{"type":"OnRamp","data":{"amount":"1000","user_id":"alice","txn_id":"t1"}}}"#,
+        r#"{"seq":3,"timestamp":1700000070000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100","quantity":"2","side":"BUY","user_id":"alice"}}}"#,
+    ];
+    let (mut engine, bus) = engine_with_journal("replay-time", &(journal.join("\n") + "\n"));
+
+    bus.push_command(
+        "c1",
+        MessageFromApi::GetKlines {
+            data: GetKlines {
+                market: "TATA_INR".to_string(),
+                interval: KlineInterval::OneMinute,
+                start_time: None,
+                end_time: None,
+            },
+        },
+    );
+    drain(&mut engine, &bus);
+
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::Klines { payload })) => {
+            assert_eq!(payload.klines.len(), 1);
+            let kline = &payload.klines[0];
+            assert_eq!(kline.open_time, 1_700_000_040_000);
+            assert_eq!((kline.c.as_str(), kline.v.as_str()), ("100.00", "2"));
+        }
+        other => panic!("unexpected klines reply {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_commands_get_an_error_reply() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("malformed");
+
+    bus.push_raw(r#"{"clientId":"c1","message":{"Unknown":{}}}"#.to_string());
+    bus.push_raw("not json".to_string());
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].0, "c1");
+    assert!(matches!(replies[0].1, MessageToApi::Error { .. }));
+}
+
+#[test]
+fn market_orders_skipping_own_orders_lock_what_they_spend() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("market-stp");
+
+    bus.push_command("c1", on_ramp("alice", "5000"));
+    // alice buys what she later offers
+    bus.push_command("c2", limit(Kind::SELL, "default_user", "100", "10"));
+    bus.push_command("c3", limit(Kind::BUY, "alice", "100", "10"));
+    bus.push_command("c4", limit(Kind::SELL, "alice", "100", "10"));
+    bus.push_command("c5", limit(Kind::SELL, "default_user", "99", "5"));
+    bus.push_command(
+        "c6",
+        market(
+            Kind::BUY,
+            "default_user",
+            "10",
+            SelfTradePrevention::CancelOldest,
+        ),
+    );
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    let (_, reply) = replies
+        .iter()
+        .find(|(client_id, _)| client_id == "c6")
+        .expect("no reply to the market order");
+    match reply {
+        MessageToApi::OrderPlaced { payload } => {
+            assert_eq!(payload.executed_qty, "10");
+            assert_eq!(payload.fills[0].price, "100.00");
+            assert_eq!(payload.self_trade_cancels.len(), 1);
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+    let balances = engine.balances("default_user").balances;
+    assert_eq!(balances["INR"].locked, "0");
+}
+
+#[test]
+fn market_orders_report_what_they_could_not_fill() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("market-remainder");
+
+    bus.push_command(
+        "c1",
+        market(Kind::SELL, "default_user", "5", SelfTradePrevention::None),
+    );
+    bus.push_command("c2", limit(Kind::SELL, "default_user", "100", "4"));
+    bus.push_command("c3", on_ramp("alice", "5000"));
+    bus.push_command(
+        "c4",
+        market(Kind::BUY, "alice", "10", SelfTradePrevention::None),
+    );
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    let reply = |id: &str| {
+        &replies
+            .iter()
+            .find(|(client_id, _)| client_id == id)
+            .unwrap()
+            .1
+    };
+    match reply("c1") {
+        MessageToApi::OrderRejected { payload } => {
+            assert_eq!(payload.reason, RejectReason::NoLiquidity)
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+    match reply("c4") {
+        MessageToApi::OrderPlaced { payload } => {
+            assert_eq!(payload.executed_qty, "4");
+            assert_eq!(payload.cancelled_qty, "6");
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+    let balances = engine.balances("alice").balances;
+    assert_eq!(balances["INR"].available, "4600");
+    assert_eq!(balances["INR"].locked, "0");
+}
+
+#[test]
+fn on_ramps_past_the_decimal_range_are_refused() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("on-ramp-overflow");
+
+    for client_id in ["c1", "c2"] {
+        bus.push_command(client_id, on_ramp("alice", "100000000000"));
+    }
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    assert!(matches!(replies[0].1, MessageToApi::Balances { .. }));
+    assert!(matches!(replies[1].1, MessageToApi::Error { .. }));
+    assert_eq!(
+        engine.balances("alice").balances["INR"].available,
+        "100000000000"
+    );
+}
+
+#[test]
+fn journal_entries_listed_in_journal_skip_are_not_replayed() {
+    let _env = ENV.lock().unwrap();
+    let journal = [
+        r#"{"seq":1,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100","quantity":"2","side":"SELL","user_id":"default_user"}}}"#,
+        r#"{"seq":2,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"101","quantity":"3","side":"SELL","user_id":"default_user"}}}"#,
+    ];
+    std::env::set_var("JOURNAL_SKIP", "1");
+    let (mut engine, bus) = engine_with_journal("replay-skip", &(journal.join("\n") + "\n"));
+    std::env::remove_var("JOURNAL_SKIP");
+
+    bus.push_command("c1", depth());
+    drain(&mut engine, &bus);
+
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::Depth { payload })) => {
+            assert_eq!(payload.asks, vec![("101.00".to_string(), "3".to_string())]);
+        }
+        other => panic!("unexpected depth reply {:?}", other),
+    }
+}
+
+#[test]
+fn klines_missing_from_an_old_snapshot_are_rebuilt_from_the_journal() {
+    let _env = ENV.lock().unwrap();
+    let journal = [
+        r#"{"seq":1,"timestamp":1700000065000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100","quantity":"2","side":"SELL","user_id":"default_user"}}}"#,
+        r#"{"seq":2,"timestamp":1700000065000,"command":{"type":"OnRamp","data":{"amount":"1000","user_id":"alice","txn_id":"t1"}}}"#,
+        r#"{"seq":3,"timestamp":1700000070000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100","quantity":"2","side":"BUY","user_id":"alice"}}}"#,
+    ];
+    // a pre-kline snapshot taken after the trade, so replay alone never sees it
+    let snapshot = r#"{"orderbooks":{},"balances":{},"journal_seq":3}"#;
+    let (mut engine, bus) = engine_with_state(
+        "kline-backfill",
+        &[("snapshot-00000000000000000003.json", snapshot)],
+        &(journal.join("\n") + "\n"),
+    );
+
+    bus.push_command(
+        "c1",
+        MessageFromApi::GetKlines {
+            data: GetKlines {
+                market: "TATA_INR".to_string(),
+                interval: KlineInterval::OneHour,
+                start_time: None,
+                end_time: None,
+            },
+        },
+    );
+    drain(&mut engine, &bus);
+
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::Klines { payload })) => {
+            assert_eq!(payload.klines.len(), 1);
+            assert_eq!(payload.klines[0].n, 1);
+        }
+        other => panic!("unexpected klines reply {:?}", other),
+    }
+}
+
+#[test]
+fn post_only_replies_say_where_the_order_rests_or_why_it_was_cancelled() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("post-only");
+
+    let post_only = |time_in_force| {
+        with(limit(Kind::SELL, "default_user", "100", "1"), |order| {
+            order.time_in_force = time_in_force
+        })
+    };
+    bus.push_command("c1", on_ramp("alice", "100"));
+    bus.push_command("c2", limit(Kind::BUY, "alice", "100", "1"));
+    bus.push_command("c3", post_only(TimeInForce::PostOnlySlide));
+    bus.push_command("c4", post_only(TimeInForce::PostOnly));
+    drain(&mut engine, &bus);
+
+    let replies = bus.take_api_messages();
+    match &replies[2] {
+        (_, MessageToApi::OrderPlaced { payload }) => {
+            assert_eq!(payload.outcome, OrderOutcome::Repriced);
+            assert_eq!(payload.resting_price.as_deref(), Some("100.05"));
+        }
+        other => panic!("unexpected slide reply {:?}", other),
+    }
+    match &replies[3] {
+        (_, MessageToApi::OrderCancelled { payload }) => {
+            assert_eq!(payload.reason, CancelReason::PostOnlyRejected);
+        }
+        other => panic!("unexpected post-only reply {:?}", other),
+    }
+}
+
+#[test]
+fn self_trade_quantity_is_not_reported_as_cancelled_twice() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("self-trade-db");
+
+    let buy = with(limit(Kind::BUY, "default_user", "100", "6"), |order| {
+        order.self_trade_prevention = SelfTradePrevention::Decrement
+    });
+    bus.push_command("c1", on_ramp("default_user", "600"));
+    bus.push_command("c2", limit(Kind::SELL, "default_user", "100", "4"));
+    bus.push_command("c3", buy);
+    drain(&mut engine, &bus);
+
+    let update = bus
+        .take_db_messages()
+        .into_iter()
+        .rev()
+        .find_map(|msg| match msg {
+            DbMessage::OrderUpdate { data } if data.side == Some(Kind::BUY) => Some(data),
+            _ => None,
+        })
+        .expect("no db update for the buy order");
+    // four of the six cancelled against the resting sell, the other two rest
+    assert_eq!(update.quantity, Some(Decimal::from(2)));
+    assert_eq!(update.executed_qty, Decimal::ZERO);
+    assert_eq!(update.cancelled_qty, None);
+}
+
+#[test]
+fn unfunded_and_over_precise_orders_are_rejected_with_a_reason() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("reject-reasons");
+
+    bus.push_command("c1", limit(Kind::BUY, "alice", "100", "1"));
+    bus.push_command("c2", limit(Kind::SELL, "default_user", "100.001", "1"));
+    drain(&mut engine, &bus);
+
+    let reasons: Vec<_> = bus
+        .take_api_messages()
+        .into_iter()
+        .map(|(_, reply)| match reply {
+            MessageToApi::OrderRejected { payload } => payload.reason,
+            other => panic!("unexpected reply {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            RejectReason::InsufficientFunds,
+            RejectReason::InvalidPrecision
+        ]
+    );
+}
+
+#[test]
+fn a_restart_keeps_the_snapshot_market_status_over_the_config_file() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("status-restart");
+
+    bus.push_command(
+        "c1",
+        MessageFromApi::SetMarketStatus {
+            data: SetMarketStatus {
+                market: "TATA_INR".to_string(),
+                status: MarketStatus::Halted,
+            },
+        },
+    );
+    drain(&mut engine, &bus);
+    engine.snapshot();
+    // the snapshot covers everything journaled so far, starting markets included
+    let journaled = std::fs::read_to_string(std::env::var("JOURNAL_PATH").unwrap())
+        .unwrap()
+        .lines()
+        .count();
+    let snapshot_dir = std::path::PathBuf::from(std::env::var("SNAPSHOT_DIR").unwrap());
+    let written = snapshot_dir.join(format!("snapshot-{:020}.snap", journaled));
+    for _ in 0..100 {
+        if written.exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // nothing is journaled past the snapshot, so only the snapshot knows about the halt
+    let bus = Arc::new(InMemoryBus::new());
+    let mut restarted = Engine::with_bus(bus.clone());
+    bus.push_command("c2", limit(Kind::SELL, "default_user", "100", "1"));
+    drain(&mut restarted, &bus);
+
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::OrderRejected { payload })) => {
+            assert_eq!(payload.reason, RejectReason::MarketNotTrading);
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+}
+
+#[test]
+fn replay_uses_the_journaled_market_definition_over_the_config_file() {
+    let _env = ENV.lock().unwrap();
+    // TATA_INR as first started, on a whole-rupee tick the config file has since changed
+    let journal = [
+        r#"{"seq":1,"timestamp":1700000065000,"command":{"type":"AddMarket","data":{"symbol":"TATA_INR","base_asset":"TATA","quote_asset":"INR","precision":{"price_decimals":2,"quantity_decimals":0},"rules":{"tick_size":"1","lot_size":"1","min_quantity":"1","max_quantity":"100000","min_notional":"100"},"status":"Trading"}}}"#,
+        r#"{"seq":2,"timestamp":1700000065000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"100.05","quantity":"2","side":"SELL","user_id":"default_user"}}}"#,
+        r#"{"seq":3,"timestamp":1700000065000,"command":{"type":"CreateOrder","data":{"market":"TATA_INR","price":"101","quantity":"3","side":"SELL","user_id":"default_user"}}}"#,
+    ];
+    let (mut engine, bus) = engine_with_journal("journaled-market", &(journal.join("\n") + "\n"));
+
+    bus.push_command("c1", depth());
+    drain(&mut engine, &bus);
+
+    match bus.take_api_messages().pop() {
+        Some((_, MessageToApi::Depth { payload })) => {
+            assert_eq!(payload.asks, vec![("101.00".to_string(), "3".to_string())]);
+        }
+        other => panic!("unexpected depth reply {:?}", other),
+    }
+    // the config file's other markets were journaled at startup, TATA_INR only once
+    let journaled = std::fs::read_to_string(std::env::var("JOURNAL_PATH").unwrap()).unwrap();
+    assert_eq!(journaled.matches(r#""symbol":"TATA_INR""#).count(), 1);
+    assert!(journaled.contains(r#""symbol":"NVIDIA_INR""#));
+}
+
+#[test]
+fn db_updates_are_keyed_by_the_journal_entry_that_caused_them() {
+    let _env = ENV.lock().unwrap();
+    let (mut engine, bus) = engine_with_bus("db-ids");
+
+    bus.push_command("c1", limit(Kind::SELL, "default_user", "100", "2"));
+    bus.push_command("c2", on_ramp("alice", "1000"));
+    bus.push_command("c3", limit(Kind::BUY, "alice", "100", "2"));
+    drain(&mut engine, &bus);
+
+    let journaled = std::fs::read_to_string(std::env::var("JOURNAL_PATH").unwrap())
+        .unwrap()
+        .lines()
+        .count();
+    let ids: Vec<_> = bus
+        .take_db_entries()
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect();
+    // the buy's own update, the maker's fill and the trade
+    let buy = journaled;
+    let sell = journaled - 2;
+    assert_eq!(
+        ids,
+        vec![
+            format!("{}:1", sell),
+            format!("{}:1", buy),
+            format!("{}:2", buy),
+            format!("{}:3", buy),
+        ]
+    );
+}
+
+// an `InMemoryBus` that has lost a db update
+struct FailingBus(InMemoryBus);
+
+impl MessageBus for FailingBus {
+    fn push_to_db(&self, entry: &protocol::db::DbEntry) -> Result<(), BusError> {
+        self.0.push_to_db(entry)
+    }
+
+    fn publish_to_ws(&self, channel: &str, msg: &WsMessage) -> Result<(), BusError> {
+        self.0.publish_to_ws(channel, msg)
+    }
+
+    fn send_to_api(&self, client_id: &str, msg: &MessageToApi) -> Result<(), BusError> {
+        self.0.send_to_api(client_id, msg)
+    }
+
+    fn receive_command(&self, timeout: Duration) -> Result<Option<String>, BusError> {
+        self.0.receive_command(timeout)
+    }
+
+    fn status(&self) -> Result<(), BusError> {
+        Err(BusError::QueueFull)
+    }
+}
+
+#[test]
+fn commands_are_refused_once_the_bus_has_lost_a_db_update() {
+    let _env = ENV.lock().unwrap();
+    let (_, _) = engine_with_bus("bus-failed");
+    let bus = Arc::new(FailingBus(InMemoryBus::new()));
+    let mut engine = Engine::with_bus(bus.clone());
+
+    bus.0
+        .push_command("c1", limit(Kind::SELL, "default_user", "100", "2"));
+    while let Some(payload) = bus.receive_command(Duration::ZERO).unwrap() {
+        engine.handle_payload(&payload);
+    }
+
+    match bus.0.take_api_messages().pop() {
+        Some((_, MessageToApi::Error { payload })) => {
+            assert!(payload.message.contains("not accepting commands"));
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+    assert!(bus.0.take_db_messages().is_empty());
+}
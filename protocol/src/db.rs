@@ -0,0 +1,43 @@
+use crate::decimal::Decimal;
+use crate::order::Kind;
+use serde::{Deserialize, Serialize};
+
+/// What is pushed onto the `db_processor` list: an update plus the id the db processor
+/// deduplicates on, as a push retried after a dropped connection may arrive twice.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DbEntry {
+    pub id: String, // "{journal seq}:{n}", the nth update the journaled command caused
+    #[serde(flatten)]
+    pub message: DbMessage,
+}
+
+/// Updates for the db processor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum DbMessage {
+    TradeAdded { data: TradeAdded },
+    OrderUpdate { data: OrderUpdate },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TradeAdded {
+    pub id: String,
+    pub is_buyer_maker: bool,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub quotequantity: Decimal,
+    pub timestamp: u64, // ms since the epoch
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderUpdate {
+    pub order_id: String,
+    pub executed_qty: Decimal,
+    pub market: Option<String>,
+    pub price: Option<Decimal>,
+    pub quantity: Option<Decimal>,
+    pub side: Option<Kind>,
+    #[serde(default)]
+    pub cancelled_qty: Option<Decimal>,
+}
@@ -0,0 +1,405 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Number of fractional digits every `Decimal` carries internally. Market precision is
+/// always at most this, so prices and quantities of any market share one representation.
+pub const SCALE: u32 = 8;
+const ONE: u64 = 10u64.pow(SCALE);
+
+/// Unsigned fixed-point number stored as an integer count of 10^-8 units.
+///
+/// Serialized as a decimal string ("101.25") so no precision is lost over JSON, and as the
+/// raw unit count in binary formats.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+pub struct Decimal(u64);
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal(0);
+    pub const MAX: Decimal = Decimal(u64::MAX);
+
+    /// `mantissa` scaled down by `decimals` digits, so `Decimal::new(5, 2)` is 0.05.
+    pub const fn new(mantissa: u64, decimals: u32) -> Self {
+        Decimal(mantissa * 10u64.pow(SCALE - decimals))
+    }
+
+    pub const fn from_units(units: u64) -> Self {
+        Decimal(units)
+    }
+
+    pub const fn units(&self) -> u64 {
+        self.0
+    }
+
+    /// Smallest step representable with `decimals` fractional digits, e.g. 0.01 for 2.
+    pub fn step(decimals: u32) -> Self {
+        Decimal(10u64.pow(SCALE - decimals.min(SCALE)))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether the value needs no more than `decimals` fractional digits.
+    pub fn fits(&self, decimals: u32) -> bool {
+        self.is_multiple_of(Self::step(decimals))
+    }
+
+    /// Whether the value is a whole number of `step`s, e.g. a tick or lot size.
+    pub fn is_multiple_of(&self, step: Decimal) -> bool {
+        step.0 != 0 && self.0.is_multiple_of(step.0)
+    }
+
+    /// Rounds down to a whole number of `step`s.
+    pub fn floor_to(&self, step: Decimal) -> Self {
+        if step.0 == 0 {
+            return *self;
+        }
+        Decimal(self.0 - self.0 % step.0)
+    }
+
+    /// Truncates to `decimals` fractional digits.
+    pub fn floor(&self, decimals: u32) -> Self {
+        self.floor_to(Self::step(decimals))
+    }
+
+    pub fn checked_add(self, rhs: Decimal) -> Option<Decimal> {
+        self.0.checked_add(rhs.0).map(Decimal)
+    }
+
+    pub fn checked_sub(self, rhs: Decimal) -> Option<Decimal> {
+        self.0.checked_sub(rhs.0).map(Decimal)
+    }
+
+    pub fn saturating_add(self, rhs: Decimal) -> Decimal {
+        Decimal(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Decimal) -> Decimal {
+        Decimal(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Product truncated to 8 decimals, `None` on overflow.
+    pub fn checked_mul(self, rhs: Decimal) -> Option<Decimal> {
+        let product = self.0 as u128 * rhs.0 as u128 / ONE as u128;
+        u64::try_from(product).ok().map(Decimal)
+    }
+
+    /// Quotient truncated to 8 decimals, `None` on overflow or division by zero.
+    pub fn checked_div(self, rhs: Decimal) -> Option<Decimal> {
+        if rhs.0 == 0 {
+            return None;
+        }
+        let quotient = self.0 as u128 * ONE as u128 / rhs.0 as u128;
+        u64::try_from(quotient).ok().map(Decimal)
+    }
+
+    /// `self * numerator / denominator` without going through a `Decimal` ratio.
+    pub fn checked_mul_ratio(self, numerator: u64, denominator: u64) -> Option<Decimal> {
+        if denominator == 0 {
+            return None;
+        }
+        let value = self.0 as u128 * numerator as u128 / denominator as u128;
+        u64::try_from(value).ok().map(Decimal)
+    }
+
+    /// Formats with exactly `decimals` fractional digits, truncating anything finer.
+    pub fn format(&self, decimals: u32) -> String {
+        let decimals = decimals.min(SCALE);
+        let integer = self.0 / ONE;
+        if decimals == 0 {
+            return integer.to_string();
+        }
+        let fraction = (self.0 % ONE) / 10u64.pow(SCALE - decimals);
+        format!(
+            "{}.{:0width$}",
+            integer,
+            fraction,
+            width = decimals as usize
+        )
+    }
+}
+
+impl From<u64> for Decimal {
+    fn from(value: u64) -> Self {
+        Decimal(value.checked_mul(ONE).expect("decimal overflow"))
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, rhs: Decimal) -> Decimal {
+        self.checked_add(rhs).expect("decimal overflow")
+    }
+}
+
+impl AddAssign for Decimal {
+    fn add_assign(&mut self, rhs: Decimal) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, rhs: Decimal) -> Decimal {
+        self.checked_sub(rhs).expect("decimal underflow")
+    }
+}
+
+impl SubAssign for Decimal {
+    fn sub_assign(&mut self, rhs: Decimal) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, rhs: Decimal) -> Decimal {
+        self.checked_mul(rhs).expect("decimal overflow")
+    }
+}
+
+impl Sum for Decimal {
+    fn sum<I: Iterator<Item = Decimal>>(iter: I) -> Decimal {
+        iter.fold(Decimal::ZERO, |acc, x| acc + x)
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Shortest exact representation: "101.5", "3", "0.00000001".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let formatted = self.format(SCALE);
+        let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+        write!(f, "{}", trimmed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDecimalError(String);
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal: {}", self.0)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDecimalError(s.to_string());
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || fraction.len() > SCALE as usize
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(err());
+        }
+
+        let integer: u64 = if integer.is_empty() {
+            0
+        } else {
+            integer.parse().map_err(|_| err())?
+        };
+        let fraction: u64 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<u64>().map_err(|_| err())? * 10u64.pow(SCALE - fraction.len() as u32)
+        };
+
+        integer
+            .checked_mul(ONE)
+            .and_then(|units| units.checked_add(fraction))
+            .map(Decimal)
+            .ok_or_else(err)
+    }
+}
+
+impl Serialize for Decimal {
+    // binary formats get the raw units
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DecimalVisitor;
+
+        impl Visitor<'_> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal string or a whole number")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            // TOML integers arrive signed
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+                let v = u64::try_from(v).map_err(|_| E::custom("decimal must not be negative"))?;
+                self.visit_u64(v)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+                v.checked_mul(ONE)
+                    .map(Decimal)
+                    .ok_or_else(|| E::custom("decimal overflow"))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(DecimalVisitor)
+        } else {
+            u64::deserialize(deserializer).map(Decimal)
+        }
+    }
+}
+
+/// Fractional digits a market accepts for prices and quantities.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Precision {
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision {
+            price_decimals: 2,
+            quantity_decimals: 2,
+        }
+    }
+}
+
+impl Precision {
+    pub fn price(&self, price: Decimal) -> String {
+        price.format(self.price_decimals)
+    }
+
+    pub fn quantity(&self, quantity: Decimal) -> String {
+        quantity.format(self.quantity_decimals)
+    }
+
+    // quote amounts carry the combined precision of price and quantity
+    pub fn quote(&self, quote: Decimal) -> String {
+        quote.format(self.price_decimals + self.quantity_decimals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_up_to_eight_fractional_digits() {
+        assert_eq!(d("101.5"), Decimal::new(1015, 1));
+        assert_eq!(d("3"), Decimal::from(3));
+        assert_eq!(d(".25"), Decimal::new(25, 2));
+        assert_eq!(d("7."), Decimal::from(7));
+        assert_eq!(d("0.00000001"), Decimal::from_units(1));
+        assert_eq!(d("184467440737.09551615"), Decimal::from_units(u64::MAX));
+
+        for invalid in [
+            "",
+            ".",
+            "-1",
+            "1.000000001",
+            "1e5",
+            "1.2.3",
+            " 1",
+            "184467440738",
+        ] {
+            assert!(invalid.parse::<Decimal>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn formats_shortest_or_with_fixed_decimals() {
+        assert_eq!(d("101.50").to_string(), "101.5");
+        assert_eq!(d("3.000").to_string(), "3");
+        assert_eq!(Decimal::ZERO.to_string(), "0");
+        assert_eq!(d("0.00000001").to_string(), "0.00000001");
+
+        assert_eq!(d("101.5").format(2), "101.50");
+        assert_eq!(d("101.59").format(1), "101.5");
+        assert_eq!(d("101.5").format(0), "101");
+        assert_eq!(d("1").format(12), "1.00000000");
+
+        let precision = Precision {
+            price_decimals: 2,
+            quantity_decimals: 1,
+        };
+        assert_eq!(precision.price(d("99.9")), "99.90");
+        assert_eq!(precision.quantity(d("2")), "2.0");
+        assert_eq!(precision.quote(d("199.8")), "199.800");
+    }
+
+    #[test]
+    fn serializes_as_a_string_or_raw_units() {
+        assert_eq!(serde_json::to_string(&d("1.5")).unwrap(), r#""1.5""#);
+        assert_eq!(
+            serde_json::from_str::<Decimal>(r#""1.5""#).unwrap(),
+            d("1.5")
+        );
+        assert_eq!(serde_json::from_str::<Decimal>("2").unwrap(), d("2"));
+        assert!(serde_json::from_str::<Decimal>("-2").is_err());
+
+        let bytes = bincode::serialize(&d("1.5")).unwrap();
+        assert_eq!(bytes, 150_000_000u64.to_le_bytes());
+        assert_eq!(bincode::deserialize::<Decimal>(&bytes).unwrap(), d("1.5"));
+    }
+
+    #[test]
+    fn checked_arithmetic_reports_overflow() {
+        assert_eq!(d("1.5").checked_add(d("2.25")), Some(d("3.75")));
+        assert_eq!(Decimal::MAX.checked_add(Decimal::from_units(1)), None);
+        assert_eq!(Decimal::MAX.saturating_add(d("1")), Decimal::MAX);
+        assert_eq!(d("1").checked_sub(d("2")), None);
+        assert_eq!(d("1").saturating_sub(d("2")), Decimal::ZERO);
+
+        assert_eq!(d("1.5").checked_mul(d("2.5")), Some(d("3.75")));
+        assert_eq!(d("0.00000001").checked_mul(d("0.5")), Some(Decimal::ZERO));
+        assert_eq!(d("1000000").checked_mul(d("1000000")), None);
+
+        assert_eq!(d("10").checked_div(d("4")), Some(d("2.5")));
+        assert_eq!(d("1").checked_div(d("3")), Some(d("0.33333333")));
+        assert_eq!(d("1").checked_div(Decimal::ZERO), None);
+        assert_eq!(d("100000000000").checked_div(d("0.1")), None);
+
+        assert_eq!(d("200").checked_mul_ratio(25, 10_000), Some(d("0.5")));
+        assert_eq!(d("1").checked_mul_ratio(1, 0), None);
+    }
+
+    #[test]
+    fn rounds_and_checks_steps() {
+        assert_eq!(d("7.3").floor_to(d("0.5")), d("7"));
+        assert_eq!(d("7.3").floor_to(Decimal::ZERO), d("7.3"));
+        assert_eq!(d("7.39").floor(1), d("7.3"));
+
+        assert!(d("100.05").is_multiple_of(d("0.05")));
+        assert!(!d("100.01").is_multiple_of(d("0.05")));
+        assert!(!d("1").is_multiple_of(Decimal::ZERO));
+        assert!(d("1.25").fits(2));
+        assert!(!d("1.25").fits(1));
+    }
+}
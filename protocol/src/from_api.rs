@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use crate::decimal::Decimal;
+use crate::kline::KlineInterval;
+use crate::markets::{MarketConfig, MarketStatus};
+use crate::order::{Kind, OrderInputSchema, OrderType, SelfTradePrevention, TimeInForce};
+/// Requests to the engine, serialized as `{"type": "CreateOrder", "data": {...}}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum MessageFromApi {
+    CreateOrder { data: CreateOrder },
+    CancelOrder { data: CancelOrder },
+    OnRamp { data: OnRamp },
+    GetDepth { data: GetDepth },
+    GetOpenOrders { data: GetOpenOrders },
+    GetBalances { data: GetBalances },
+    GetTicker { data: GetTicker },
+    GetKlines { data: GetKlines },
+    // admin commands
+    AddMarket { data: MarketConfig },
+    SetMarketStatus { data: SetMarketStatus },
+}
+
+/// What the API pushes onto the "messages" list: a command plus the channel to reply on.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IncomingMessage {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub message: MessageFromApi,
+}
+
+impl MessageFromApi {
+    /// Whether the message changes engine state and therefore goes through the journal.
+    pub fn is_command(&self) -> bool {
+        !matches!(
+            self,
+            MessageFromApi::GetDepth { .. }
+                | MessageFromApi::GetOpenOrders { .. }
+                | MessageFromApi::GetBalances { .. }
+                | MessageFromApi::GetTicker { .. }
+                | MessageFromApi::GetKlines { .. }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+
+pub struct CreateOrder {
+    pub market: String,
+    #[serde(default)]
+    pub order_type: OrderType,
+    // ignored by market orders, which always behave as IOC
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub self_trade_prevention: SelfTradePrevention,
+    // limit price, unused by market orders
+    #[serde(default)]
+    pub price: Decimal,
+    // base quantity, unused when a market order sets quote_quantity
+    #[serde(default)]
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub quote_quantity: Option<Decimal>,
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    #[serde(default)]
+    pub max_slippage_bps: Option<u64>,
+    pub side: Kind,
+    pub user_id: String,
+}
+
+impl From<OrderInputSchema> for CreateOrder {
+    fn from(input: OrderInputSchema) -> Self {
+        CreateOrder {
+            market: format!("{}_{}", input.base_asset, input.quote_asset),
+            order_type: input.order_type,
+            time_in_force: input.time_in_force,
+            self_trade_prevention: input.self_trade_prevention,
+            price: input.price,
+            quantity: input.quantity,
+            quote_quantity: input.quote_quantity,
+            protection_price: input.protection_price,
+            max_slippage_bps: input.max_slippage_bps,
+            side: input.side,
+            user_id: input.user_id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CancelOrder {
+    pub order_id: String,
+    pub user_id: String,
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OnRamp {
+    pub amount: Decimal,
+    pub user_id: String,
+    pub txn_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetDepth {
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetOpenOrders {
+    pub user_id: String,
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBalances {
+    pub user_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetTicker {
+    pub market: String,
+}
+
+// candles opening between `start_time` and `end_time` inclusive, in ms since the epoch
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetKlines {
+    pub market: String,
+    pub interval: KlineInterval,
+    #[serde(default)]
+    pub start_time: Option<u64>,
+    #[serde(default)]
+    pub end_time: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetMarketStatus {
+    pub market: String,
+    pub status: MarketStatus,
+}
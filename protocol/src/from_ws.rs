@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// A websocket client request, e.g. `{"method": "SUBSCRIBE", "params": ["trade@TATA_INR"], "id": 1}`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WsRequest {
+    pub method: WsMethod,
+    #[serde(default)]
+    pub params: Vec<String>, // stream names
+    #[serde(default)]
+    pub id: Option<u64>, // echoed back in the response
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum WsMethod {
+    SUBSCRIBE,
+    UNSUBSCRIBE,
+}
+
+/// Answer to a `WsRequest`; `error` is set when the request was refused as a whole.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WsResponse {
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Candle widths, written as in the `kline@TATA_INR_1m` stream name. Candles start on
+/// multiples of their width since the Unix epoch, so days run midnight to midnight UTC.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub enum KlineInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl KlineInterval {
+    pub const ALL: [KlineInterval; 5] = [
+        KlineInterval::OneMinute,
+        KlineInterval::FiveMinutes,
+        KlineInterval::FifteenMinutes,
+        KlineInterval::OneHour,
+        KlineInterval::OneDay,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KlineInterval::OneMinute => "1m",
+            KlineInterval::FiveMinutes => "5m",
+            KlineInterval::FifteenMinutes => "15m",
+            KlineInterval::OneHour => "1h",
+            KlineInterval::OneDay => "1d",
+        }
+    }
+
+    pub fn millis(&self) -> u64 {
+        const MINUTE: u64 = 60 * 1000;
+        match self {
+            KlineInterval::OneMinute => MINUTE,
+            KlineInterval::FiveMinutes => 5 * MINUTE,
+            KlineInterval::FifteenMinutes => 15 * MINUTE,
+            KlineInterval::OneHour => 60 * MINUTE,
+            KlineInterval::OneDay => 24 * 60 * MINUTE,
+        }
+    }
+
+    /// Start of the candle `timestamp` (ms since the epoch) falls into.
+    pub fn open_time(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.millis()
+    }
+}
+
+impl fmt::Display for KlineInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseKlineIntervalError(String);
+
+impl fmt::Display for ParseKlineIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown kline interval: {}", self.0)
+    }
+}
+
+impl FromStr for KlineInterval {
+    type Err = ParseKlineIntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KlineInterval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .ok_or_else(|| ParseKlineIntervalError(s.to_string()))
+    }
+}
@@ -0,0 +1,10 @@
+pub mod db;
+pub mod decimal;
+pub mod from_api;
+pub mod from_ws;
+pub mod kline;
+pub mod markets;
+pub mod order;
+pub mod to_api;
+pub mod to_ws;
+pub mod transport;
@@ -0,0 +1,314 @@
+use crate::decimal::{Decimal, Precision, SCALE};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum MarketStatus {
+    #[default]
+    Trading,
+    // resting orders can still be cancelled, nothing new is accepted
+    CancelOnly,
+    Halted,
+}
+
+impl MarketStatus {
+    pub fn accepts_orders(&self) -> bool {
+        matches!(self, MarketStatus::Trading)
+    }
+
+    pub fn accepts_cancels(&self) -> bool {
+        !matches!(self, MarketStatus::Halted)
+    }
+}
+
+/// A tradable pair as declared in the markets config, e.g. `TATA_INR`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MarketConfig {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    #[serde(default)]
+    pub precision: Precision,
+    pub rules: TradingRules,
+    #[serde(default)]
+    pub status: MarketStatus,
+}
+
+impl MarketConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid =
+            |reason: &str| Err(ConfigError::Invalid(format!("{}: {}", self.symbol, reason)));
+        let Precision {
+            price_decimals,
+            quantity_decimals,
+        } = self.precision;
+
+        if self.symbol.is_empty() || self.base_asset.is_empty() || self.quote_asset.is_empty() {
+            return invalid("symbol and assets must not be empty");
+        }
+        if price_decimals + quantity_decimals > SCALE {
+            return invalid("price and quantity decimals together exceed the supported scale");
+        }
+        if self.rules.tick_size.is_zero() || !self.rules.tick_size.fits(price_decimals) {
+            return invalid("tick size must be a positive multiple of the price precision");
+        }
+        if self.rules.lot_size.is_zero() || !self.rules.lot_size.fits(quantity_decimals) {
+            return invalid("lot size must be a positive multiple of the quantity precision");
+        }
+        if self.rules.min_quantity > self.rules.max_quantity {
+            return invalid("min quantity is above max quantity");
+        }
+        Ok(())
+    }
+}
+
+// the config file is a list of `[[market]]` tables
+#[derive(Deserialize)]
+struct MarketsFile {
+    #[serde(default, rename = "market")]
+    markets: Vec<MarketConfig>,
+}
+
+/// Reads market definitions from a TOML file, or JSON when the extension is `.json`.
+pub fn load_markets(path: &Path) -> Result<Vec<MarketConfig>, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => {
+            checked(serde_json::from_str(&contents).map_err(|e| ConfigError::Parse(e.to_string()))?)
+        }
+        _ => parse_markets(&contents),
+    }
+}
+
+/// Parses market definitions in the TOML format of `load_markets`.
+pub fn parse_markets(contents: &str) -> Result<Vec<MarketConfig>, ConfigError> {
+    checked(toml::from_str(contents).map_err(|e| ConfigError::Parse(e.to_string()))?)
+}
+
+fn checked(file: MarketsFile) -> Result<Vec<MarketConfig>, ConfigError> {
+    let mut symbols = HashSet::new();
+    for market in &file.markets {
+        market.validate()?;
+        if !symbols.insert(market.symbol.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "{}: defined more than once",
+                market.symbol
+            )));
+        }
+    }
+    Ok(file.markets)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read markets config: {}", e),
+            ConfigError::Parse(e) => write!(f, "failed to parse markets config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid market: {}", e),
+        }
+    }
+}
+
+/// Limits an order must satisfy before it reaches the book.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct TradingRules {
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+    pub min_quantity: Decimal,
+    pub max_quantity: Decimal,
+    pub min_notional: Decimal, // price * quantity, in the quote asset
+}
+
+impl TradingRules {
+    pub fn check_price(&self, price: Decimal) -> Result<(), RejectReason> {
+        if price.is_zero() {
+            return Err(RejectReason::InvalidPrice);
+        }
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(RejectReason::PriceNotOnTick);
+        }
+        Ok(())
+    }
+
+    pub fn check_quantity(&self, quantity: Decimal) -> Result<(), RejectReason> {
+        if quantity < self.min_quantity {
+            return Err(RejectReason::QuantityBelowMin);
+        }
+        if quantity > self.max_quantity {
+            return Err(RejectReason::QuantityAboveMax);
+        }
+        if !quantity.is_multiple_of(self.lot_size) {
+            return Err(RejectReason::QuantityNotOnLot);
+        }
+        Ok(())
+    }
+
+    pub fn check_notional(&self, notional: Decimal) -> Result<(), RejectReason> {
+        if notional < self.min_notional {
+            return Err(RejectReason::NotionalBelowMin);
+        }
+        Ok(())
+    }
+}
+
+/// Why an order was refused without touching the book.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum RejectReason {
+    InvalidPrice,
+    PriceNotOnTick,
+    QuantityNotOnLot,
+    QuantityBelowMin,
+    QuantityAboveMax,
+    NotionalBelowMin,
+    NoLiquidity,
+    InvalidPrecision,
+    MarketNotTrading,
+    InsufficientFunds,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::InvalidPrice => write!(f, "price must be greater than zero"),
+            RejectReason::PriceNotOnTick => write!(f, "price is not a multiple of the tick size"),
+            RejectReason::QuantityNotOnLot => {
+                write!(f, "quantity is not a multiple of the lot size")
+            }
+            RejectReason::QuantityBelowMin => write!(f, "quantity is below the minimum"),
+            RejectReason::QuantityAboveMax => write!(f, "quantity is above the maximum"),
+            RejectReason::NotionalBelowMin => write!(f, "order value is below the minimum"),
+            RejectReason::NoLiquidity => {
+                write!(f, "no resting orders to match within the protection price")
+            }
+            RejectReason::InvalidPrecision => {
+                write!(
+                    f,
+                    "price or quantity has more decimals than the market allows"
+                )
+            }
+            RejectReason::MarketNotTrading => write!(f, "market is not accepting orders"),
+            RejectReason::InsufficientFunds => write!(f, "insufficient funds"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn rules() -> TradingRules {
+        TradingRules {
+            tick_size: d("0.05"),
+            lot_size: d("0.5"),
+            min_quantity: d("1"),
+            max_quantity: d("100"),
+            min_notional: d("100"),
+        }
+    }
+
+    #[test]
+    fn prices_must_be_positive_and_on_the_tick() {
+        assert_eq!(rules().check_price(d("100.05")), Ok(()));
+        assert_eq!(
+            rules().check_price(Decimal::ZERO),
+            Err(RejectReason::InvalidPrice)
+        );
+        assert_eq!(
+            rules().check_price(d("100.01")),
+            Err(RejectReason::PriceNotOnTick)
+        );
+    }
+
+    #[test]
+    fn quantities_must_be_within_bounds_and_on_the_lot() {
+        assert_eq!(rules().check_quantity(d("1")), Ok(()));
+        assert_eq!(rules().check_quantity(d("100")), Ok(()));
+        assert_eq!(rules().check_quantity(d("2.5")), Ok(()));
+        assert_eq!(
+            rules().check_quantity(d("0.5")),
+            Err(RejectReason::QuantityBelowMin)
+        );
+        assert_eq!(
+            rules().check_quantity(d("100.5")),
+            Err(RejectReason::QuantityAboveMax)
+        );
+        assert_eq!(
+            rules().check_quantity(d("2.2")),
+            Err(RejectReason::QuantityNotOnLot)
+        );
+    }
+
+    #[test]
+    fn order_value_must_reach_the_minimum_notional() {
+        assert_eq!(rules().check_notional(d("100")), Ok(()));
+        assert_eq!(
+            rules().check_notional(d("99.99")),
+            Err(RejectReason::NotionalBelowMin)
+        );
+    }
+
+    #[test]
+    fn markets_with_unusable_rules_are_invalid() {
+        let market = MarketConfig {
+            symbol: "TATA_INR".to_string(),
+            base_asset: "TATA".to_string(),
+            quote_asset: "INR".to_string(),
+            precision: Precision {
+                price_decimals: 2,
+                quantity_decimals: 1,
+            },
+            rules: rules(),
+            status: MarketStatus::Trading,
+        };
+        assert!(market.validate().is_ok());
+
+        let invalid = |change: fn(&mut MarketConfig)| {
+            let mut market = market.clone();
+            change(&mut market);
+            market.validate().is_err()
+        };
+        assert!(invalid(|m| m.rules.tick_size = Decimal::ZERO));
+        assert!(invalid(|m| m.rules.tick_size = d("0.001")));
+        assert!(invalid(|m| m.rules.lot_size = d("0.25")));
+        assert!(invalid(|m| m.rules.min_quantity = d("101")));
+        assert!(invalid(|m| m.precision.price_decimals = 8));
+        assert!(invalid(|m| m.base_asset.clear()));
+    }
+
+    #[test]
+    fn configs_parse_and_reject_duplicate_symbols() {
+        let market = r#"
+            [[market]]
+            symbol = "TATA_INR"
+            base_asset = "TATA"
+            quote_asset = "INR"
+            precision = { price_decimals = 2, quantity_decimals = 0 }
+            rules = { tick_size = "0.05", lot_size = "1", min_quantity = "1", max_quantity = "100", min_notional = "100" }
+        "#;
+        let markets = parse_markets(market).unwrap();
+        assert_eq!(markets[0].symbol, "TATA_INR");
+        assert_eq!(markets[0].status, MarketStatus::Trading);
+        assert!(matches!(
+            parse_markets(&market.repeat(2)),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_markets("[[market]]"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub enum Kind {
+    BUY,
+    SELL,
+}
+
+impl Kind {
+    /// The side an order of this side trades against.
+    pub fn opposite(self) -> Self {
+        match self {
+            Kind::BUY => Kind::SELL,
+            Kind::SELL => Kind::BUY,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy, Default)]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy, Default)]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    Ioc,
+    Fok,
+    PostOnly,
+    // post-only that moves one tick behind the touch instead of being rejected
+    PostOnlySlide,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy, Default)]
+pub enum SelfTradePrevention {
+    #[default]
+    None,
+    CancelNewest,
+    CancelOldest,
+    CancelBoth,
+    Decrement,
+}
+
+impl SelfTradePrevention {
+    /// Quantities to cancel from the resting and the incoming order when both belong to the
+    /// same user, or `None` to let them trade.
+    pub fn cancels(
+        &self,
+        taker_remaining: Decimal,
+        maker_remaining: Decimal,
+    ) -> Option<(Decimal, Decimal)> {
+        match self {
+            SelfTradePrevention::None => None,
+            SelfTradePrevention::CancelNewest => Some((Decimal::ZERO, taker_remaining)),
+            SelfTradePrevention::CancelOldest => Some((maker_remaining, Decimal::ZERO)),
+            SelfTradePrevention::CancelBoth => Some((maker_remaining, taker_remaining)),
+            SelfTradePrevention::Decrement => {
+                let qty = std::cmp::min(taker_remaining, maker_remaining);
+                Some((qty, qty))
+            }
+        }
+    }
+}
+
+/// How an incoming order left the book: traded, rested, or was turned away.
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub enum OrderOutcome {
+    Filled,
+    Resting,
+    RemainderCancelled,
+    Killed,
+    PostOnlyRejected,
+    Repriced,
+    SelfTradePrevented,
+}
+
+impl OrderOutcome {
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, OrderOutcome::Killed | OrderOutcome::PostOnlyRejected)
+    }
+
+    pub fn is_resting(&self) -> bool {
+        matches!(self, OrderOutcome::Resting | OrderOutcome::Repriced)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone)]
+pub struct Order {
+    pub order_id: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled: Decimal,
+    pub side: Kind,
+    pub user_id: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Depth {
+    pub bids: Vec<(Decimal, Decimal)>, // best (highest) price first
+    pub asks: Vec<(Decimal, Decimal)>, // best (lowest) price first
+}
+
+/// Order as submitted over REST; the market is given as its two assets.
+#[derive(Serialize, Deserialize, Debug, PartialEq, PartialOrd)]
+pub struct OrderInputSchema {
+    pub base_asset: String,
+    pub quote_asset: String,
+    #[serde(default)]
+    pub price: Decimal,
+    #[serde(default)]
+    pub quantity: Decimal,
+    pub side: Kind,
+    pub user_id: String,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub self_trade_prevention: SelfTradePrevention,
+    #[serde(default)]
+    pub quote_quantity: Option<Decimal>,
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    #[serde(default)]
+    pub max_slippage_bps: Option<u64>,
+}
@@ -0,0 +1,149 @@
+use crate::decimal::Precision;
+use crate::markets::{MarketConfig, RejectReason};
+use crate::order::{Depth, Order, OrderOutcome};
+use crate::to_ws::{KlineData, TickerData};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fill {
+    pub price: String,
+    pub qty: String,
+    pub trade_id: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SelfTradeCancelled {
+    pub order_id: String,
+    pub cancelled_qty: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderPlaced {
+    pub order_id: String,
+    pub outcome: OrderOutcome,
+    // where the remainder rests, after any post-only slide; none when nothing rests
+    #[serde(default)]
+    pub resting_price: Option<String>,
+    pub executed_qty: String,
+    pub fills: Vec<Fill>,
+    #[serde(default)]
+    pub self_trade_qty: String,
+    #[serde(default)]
+    pub self_trade_cancels: Vec<SelfTradeCancelled>,
+    // the part that neither traded nor rests, e.g. what an IOC or market order could not fill
+    #[serde(default)]
+    pub cancelled_qty: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum CancelReason {
+    #[default]
+    Requested,
+    // a fill-or-kill order the book could not fill in full
+    Killed,
+    // a post-only order that would have taken liquidity
+    PostOnlyRejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderCancelled {
+    pub order_id: String,
+    pub executed_qty: String,
+    pub remaining_qty: String,
+    #[serde(default)]
+    pub reason: CancelReason,
+}
+
+// price and quantity strings formatted to the market's precision, best first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DepthPayload {
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+    // the book as of this depth update id, see `to_ws::DepthData` for resyncing against it
+    #[serde(default)]
+    pub last_update_id: u64,
+}
+
+impl DepthPayload {
+    pub fn new(depth: &Depth, precision: Precision, last_update_id: u64) -> Self {
+        let levels = |levels: &[(_, _)]| {
+            levels
+                .iter()
+                .map(|&(price, qty)| (precision.price(price), precision.quantity(qty)))
+                .collect()
+        };
+        DepthPayload {
+            bids: levels(&depth.bids),
+            asks: levels(&depth.asks),
+            last_update_id,
+        }
+    }
+}
+
+// the order broke a market trading rule and never reached the book
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderRejected {
+    pub reason: RejectReason,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenOrders {
+    pub orders: Vec<Order>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetBalance {
+    pub available: String,
+    pub locked: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Balances {
+    pub user_id: String,
+    pub balances: HashMap<String, AssetBalance>,
+}
+
+// oldest first
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Klines {
+    pub klines: Vec<KlineData>,
+}
+
+/// What an `Error` reply failed on, which the API turns into a status code.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum ErrorKind {
+    #[default]
+    Invalid,
+    NotFound,
+    Forbidden,
+    Conflict,
+    // the engine cannot take commands right now, e.g. its bus lost a db update
+    Unavailable,
+    // the engine's own fault, such as a failed journal write
+    Internal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorMessage {
+    #[serde(default)]
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// Engine replies, serialized as `{"type": "OrderPlaced", "payload": {...}}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum MessageToApi {
+    Depth { payload: DepthPayload },
+    OrderPlaced { payload: OrderPlaced },
+    OrderCancelled { payload: OrderCancelled },
+    OrderRejected { payload: OrderRejected },
+    OpenOrders { payload: OpenOrders },
+    Balances { payload: Balances },
+    Ticker { payload: TickerData },
+    Klines { payload: Klines },
+    MarketUpdated { payload: MarketConfig },
+    Error { payload: ErrorMessage },
+}
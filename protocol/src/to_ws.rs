@@ -0,0 +1,172 @@
+use crate::kline::KlineInterval;
+use serde::{Deserialize, Serialize};
+
+/// Kinds of stream clients can subscribe to, named `{kind}@{symbol}` as in `depth@TATA_INR`,
+/// or `kline@{symbol}_{interval}` as in `kline@TATA_INR_1m`. The engine publishes each
+/// stream on the Redis channel of the same name.
+pub const STREAM_KINDS: [&str; 4] = ["trade", "depth", "ticker", "kline"];
+
+pub fn is_valid_stream(stream: &str) -> bool {
+    match stream.split_once('@') {
+        Some(("kline", rest)) => rest.rsplit_once('_').is_some_and(|(symbol, interval)| {
+            !symbol.is_empty() && interval.parse::<KlineInterval>().is_ok()
+        }),
+        Some((kind, symbol)) => STREAM_KINDS.contains(&kind) && !symbol.is_empty(),
+        None => false,
+    }
+}
+
+pub fn kline_stream(symbol: &str, interval: KlineInterval) -> String {
+    format!("kline@{}_{}", symbol, interval)
+}
+
+// Default value functions
+fn default_ticker_event() -> String {
+    "ticker".to_string()
+}
+
+fn default_depth_event() -> String {
+    "depth".to_string()
+}
+
+fn default_trade_event() -> String {
+    "trade".to_string()
+}
+
+fn default_kline_event() -> String {
+    "kline".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TickerUpdateMessage {
+    pub stream: String,
+    pub data: TickerData,
+}
+
+/// Rolling 24 hour statistics of a market. Prices are absent while it had no trades.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TickerData {
+    #[serde(default)]
+    pub o: Option<String>, // open
+    pub c: Option<String>, // last
+    pub h: Option<String>,
+    pub l: Option<String>,
+    pub v: Option<String>,   // base volume
+    pub v_2: Option<String>, // quote volume
+    #[serde(rename = "P", default)]
+    pub price_change_percent: Option<String>,
+    #[serde(default)]
+    pub n: u64, // trade count
+    pub s: Option<String>,
+    pub id: usize, // last trade id
+    #[serde(default = "default_ticker_event")]
+    pub e: String, // "ticker"
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct DepthUpdateMessage {
+    pub stream: String,
+    pub data: DepthData,
+}
+
+/// Changed price levels with their new total quantity, "0" meaning the level is gone.
+///
+/// Every changed level takes the next depth update id of its market, so one update covers
+/// ids `U..=u` and the next one starts at `u + 1`. To follow the book without gaps:
+///
+/// 1. Subscribe to `depth@{symbol}` and buffer the updates.
+/// 2. Fetch `GET /api/v1/depth?symbol={symbol}` and note its `last_update_id`.
+/// 3. Drop buffered updates with `u <= last_update_id`. The first one left must have
+///    `U <= last_update_id + 1`, otherwise fetch the snapshot again.
+/// 4. Apply updates in order. An update whose `U` is not the previous `u + 1` means one was
+///    missed: discard the book and start over from step 2.
+///
+/// Ids keep counting across engine restarts. The REST snapshot only lists the best levels
+/// of each side, so updates may name levels it did not include.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct DepthData {
+    pub b: Option<Vec<(String, String)>>,
+    pub a: Option<Vec<(String, String)>>,
+    #[serde(default = "default_depth_event")]
+    pub e: String, // "depth"
+    #[serde(rename = "U", default)]
+    pub first_update_id: u64,
+    #[serde(rename = "u", default)]
+    pub last_update_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TradeAddedMessage {
+    pub stream: String,
+    pub data: TradeData,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TradeData {
+    #[serde(default = "default_trade_event")]
+    pub e: String, // "trade"
+    pub t: usize,
+    pub m: bool,
+    pub p: String,
+    pub q: String,
+    pub s: String, // symbol
+    #[serde(rename = "T", default)]
+    pub timestamp: u64, // ms since the epoch
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct KlineUpdateMessage {
+    pub stream: String,
+    pub data: KlineData,
+}
+
+/// One OHLCV candle. Live updates repeat the open candle after every trade in it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct KlineData {
+    #[serde(default = "default_kline_event")]
+    pub e: String, // "kline"
+    pub s: String, // symbol
+    pub i: KlineInterval,
+    #[serde(rename = "t")]
+    pub open_time: u64, // ms since the epoch
+    #[serde(rename = "T")]
+    pub close_time: u64, // last ms the candle covers
+    pub o: String,
+    pub h: String,
+    pub l: String,
+    pub c: String,
+    pub v: String,   // base volume
+    pub v_2: String, // quote volume
+    pub n: u64,      // trade count
+}
+
+/// Stream updates, serialized as `{"type": "DepthUpdateMessage", "data": {...}}`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type")]
+pub enum WsMessage {
+    TickerUpdateMessage { data: TickerData },
+    DepthUpdateMessage { data: DepthData },
+    TradeAddedMessage { data: TradeData },
+    KlineUpdateMessage { data: KlineData },
+}
+
+impl WsMessage {
+    /// The `{"stream": ..., "data": ...}` frame websocket clients receive.
+    pub fn to_client_json(self, stream: &str) -> serde_json::Result<String> {
+        let stream = stream.to_string();
+        match self {
+            WsMessage::TickerUpdateMessage { data } => {
+                serde_json::to_string(&TickerUpdateMessage { stream, data })
+            }
+            WsMessage::DepthUpdateMessage { data } => {
+                serde_json::to_string(&DepthUpdateMessage { stream, data })
+            }
+            WsMessage::TradeAddedMessage { data } => {
+                serde_json::to_string(&TradeAddedMessage { stream, data })
+            }
+            WsMessage::KlineUpdateMessage { data } => {
+                serde_json::to_string(&KlineUpdateMessage { stream, data })
+            }
+        }
+    }
+}
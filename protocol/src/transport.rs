@@ -0,0 +1,26 @@
+use std::env;
+
+// the engine pops commands from this list and replies on the channel named by `clientId`
+pub const MESSAGES_QUEUE: &str = "messages";
+pub const DB_QUEUE: &str = "db_processor";
+pub const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+/// Where to find Redis. `REDIS_URL` may use `rediss://` for TLS, or `REDIS_TLS=true` upgrades a
+/// plain `redis://` URL; either needs the consuming crate's `tls` feature.
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    pub url: String,
+}
+
+impl RedisConfig {
+    pub fn from_env() -> Self {
+        let mut url = env::var("REDIS_URL").unwrap_or_else(|_| DEFAULT_REDIS_URL.to_string());
+        let tls = env::var("REDIS_TLS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        if tls {
+            if let Some(rest) = url.strip_prefix("redis://") {
+                url = format!("rediss://{}", rest);
+            }
+        }
+        RedisConfig { url }
+    }
+}
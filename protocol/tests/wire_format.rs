@@ -0,0 +1,442 @@
+//! Pins the JSON every service and frontend exchanges. A failure here means the wire format
+//! changed and every consumer has to change with it.
+
+use protocol::db::{DbEntry, DbMessage, OrderUpdate, TradeAdded};
+use protocol::decimal::Decimal;
+use protocol::from_api::{CancelOrder, CreateOrder, GetDepth, IncomingMessage, MessageFromApi};
+use protocol::from_ws::{WsMethod, WsRequest, WsResponse};
+use protocol::kline::KlineInterval;
+use protocol::markets::RejectReason;
+use protocol::order::{Kind, OrderOutcome, OrderType, SelfTradePrevention, TimeInForce};
+use protocol::to_api::{
+    CancelReason, ErrorKind, ErrorMessage, Fill, MessageToApi, OrderCancelled, OrderPlaced,
+    OrderRejected,
+};
+use protocol::to_ws::{is_valid_stream, DepthData, KlineData, TickerData, TradeData, WsMessage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// encodes to exactly `expected`, and decoding `expected` encodes back to it unchanged
+fn assert_wire<T: Serialize + DeserializeOwned>(msg: &T, expected: Value) {
+    assert_eq!(serde_json::to_value(msg).unwrap(), expected);
+    let decoded: T = serde_json::from_value(expected.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+}
+
+fn decimal(s: &str) -> Decimal {
+    s.parse().unwrap()
+}
+
+#[test]
+fn create_order() {
+    let msg = MessageFromApi::CreateOrder {
+        data: CreateOrder {
+            market: "TATA_INR".to_string(),
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::PostOnly,
+            self_trade_prevention: SelfTradePrevention::CancelOldest,
+            price: decimal("100.05"),
+            quantity: decimal("3"),
+            quote_quantity: None,
+            protection_price: None,
+            max_slippage_bps: Some(50),
+            side: Kind::BUY,
+            user_id: "alice".to_string(),
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "type": "CreateOrder",
+            "data": {
+                "market": "TATA_INR",
+                "order_type": "Limit",
+                "time_in_force": "PostOnly",
+                "self_trade_prevention": "CancelOldest",
+                "price": "100.05",
+                "quantity": "3",
+                "quote_quantity": null,
+                "protection_price": null,
+                "max_slippage_bps": 50,
+                "side": "BUY",
+                "user_id": "alice"
+            }
+        }),
+    );
+}
+
+#[test]
+fn create_order_defaults_optional_fields() {
+    let msg: MessageFromApi = serde_json::from_value(json!({
+        "type": "CreateOrder",
+        "data": { "market": "TATA_INR", "price": "100", "quantity": "1", "side": "SELL", "user_id": "bob" }
+    }))
+    .unwrap();
+    let MessageFromApi::CreateOrder { data } = msg else {
+        panic!("decoded {:?}", msg);
+    };
+    assert_eq!(data.order_type, OrderType::Limit);
+    assert_eq!(data.time_in_force, TimeInForce::Gtc);
+    assert_eq!(data.self_trade_prevention, SelfTradePrevention::None);
+    assert_eq!(data.quote_quantity, None);
+}
+
+#[test]
+fn cancel_order() {
+    let msg = MessageFromApi::CancelOrder {
+        data: CancelOrder {
+            order_id: "42".to_string(),
+            user_id: "alice".to_string(),
+            market: "TATA_INR".to_string(),
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "type": "CancelOrder",
+            "data": { "order_id": "42", "user_id": "alice", "market": "TATA_INR" }
+        }),
+    );
+}
+
+#[test]
+fn incoming_envelope() {
+    let msg = IncomingMessage {
+        client_id: "api:1:7".to_string(),
+        message: MessageFromApi::GetDepth {
+            data: GetDepth {
+                market: "TATA_INR".to_string(),
+            },
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "clientId": "api:1:7",
+            "message": { "type": "GetDepth", "data": { "market": "TATA_INR" } }
+        }),
+    );
+}
+
+#[test]
+fn order_placed() {
+    let msg = MessageToApi::OrderPlaced {
+        payload: OrderPlaced {
+            order_id: "42".to_string(),
+            outcome: OrderOutcome::RemainderCancelled,
+            resting_price: None,
+            executed_qty: "2".to_string(),
+            fills: vec![Fill {
+                price: "100.05".to_string(),
+                qty: "2".to_string(),
+                trade_id: 9,
+            }],
+            self_trade_qty: "0".to_string(),
+            self_trade_cancels: vec![],
+            cancelled_qty: "1".to_string(),
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "type": "OrderPlaced",
+            "payload": {
+                "order_id": "42",
+                "outcome": "RemainderCancelled",
+                "resting_price": null,
+                "executed_qty": "2",
+                "fills": [{ "price": "100.05", "qty": "2", "trade_id": 9 }],
+                "self_trade_qty": "0",
+                "self_trade_cancels": [],
+                "cancelled_qty": "1"
+            }
+        }),
+    );
+}
+
+#[test]
+fn order_cancelled() {
+    let msg = MessageToApi::OrderCancelled {
+        payload: OrderCancelled {
+            order_id: "42".to_string(),
+            executed_qty: "1".to_string(),
+            remaining_qty: "2".to_string(),
+            reason: CancelReason::Killed,
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "type": "OrderCancelled",
+            "payload": {
+                "order_id": "42",
+                "executed_qty": "1",
+                "remaining_qty": "2",
+                "reason": "Killed"
+            }
+        }),
+    );
+}
+
+#[test]
+fn rejections_and_errors() {
+    let rejected = MessageToApi::OrderRejected {
+        payload: OrderRejected {
+            reason: RejectReason::PriceNotOnTick,
+            message: RejectReason::PriceNotOnTick.to_string(),
+        },
+    };
+    assert_wire(
+        &rejected,
+        json!({
+            "type": "OrderRejected",
+            "payload": {
+                "reason": "PriceNotOnTick",
+                "message": RejectReason::PriceNotOnTick.to_string()
+            }
+        }),
+    );
+
+    let error = MessageToApi::Error {
+        payload: ErrorMessage {
+            kind: ErrorKind::NotFound,
+            message: "market not found".to_string(),
+        },
+    };
+    assert_wire(
+        &error,
+        json!({
+            "type": "Error",
+            "payload": { "kind": "NotFound", "message": "market not found" }
+        }),
+    );
+}
+
+#[test]
+fn ws_depth_and_trade() {
+    let depth = WsMessage::DepthUpdateMessage {
+        data: DepthData {
+            b: Some(vec![("100.05".to_string(), "3".to_string())]),
+            a: None,
+            e: "depth".to_string(),
+            first_update_id: 7,
+            last_update_id: 7,
+        },
+    };
+    assert_wire(
+        &depth,
+        json!({
+            "type": "DepthUpdateMessage",
+            "data": { "b": [["100.05", "3"]], "a": null, "e": "depth", "U": 7, "u": 7 }
+        }),
+    );
+
+    let trade = WsMessage::TradeAddedMessage {
+        data: TradeData {
+            e: "trade".to_string(),
+            t: 9,
+            m: true,
+            p: "100.05".to_string(),
+            q: "2".to_string(),
+            s: "TATA_INR".to_string(),
+            timestamp: 1_700_000_000_000,
+        },
+    };
+    assert_wire(
+        &trade,
+        json!({
+            "type": "TradeAddedMessage",
+            "data": {
+                "e": "trade",
+                "t": 9,
+                "m": true,
+                "p": "100.05",
+                "q": "2",
+                "s": "TATA_INR",
+                "T": 1_700_000_000_000u64
+            }
+        }),
+    );
+}
+
+#[test]
+fn ws_ticker() {
+    let ticker = WsMessage::TickerUpdateMessage {
+        data: TickerData {
+            o: Some("100.00".to_string()),
+            c: Some("98.75".to_string()),
+            h: Some("101.00".to_string()),
+            l: Some("98.50".to_string()),
+            v: Some("12".to_string()),
+            v_2: Some("1197.50".to_string()),
+            price_change_percent: Some("-1.25".to_string()),
+            n: 4,
+            s: Some("TATA_INR".to_string()),
+            id: 9,
+            e: "ticker".to_string(),
+        },
+    };
+    assert_wire(
+        &ticker,
+        json!({
+            "type": "TickerUpdateMessage",
+            "data": {
+                "o": "100.00",
+                "c": "98.75",
+                "h": "101.00",
+                "l": "98.50",
+                "v": "12",
+                "v_2": "1197.50",
+                "P": "-1.25",
+                "n": 4,
+                "s": "TATA_INR",
+                "id": 9,
+                "e": "ticker"
+            }
+        }),
+    );
+}
+
+#[test]
+fn ws_kline() {
+    let kline = WsMessage::KlineUpdateMessage {
+        data: KlineData {
+            e: "kline".to_string(),
+            s: "TATA_INR".to_string(),
+            i: KlineInterval::OneMinute,
+            open_time: 1_700_000_040_000,
+            close_time: 1_700_000_099_999,
+            o: "100.00".to_string(),
+            h: "101.00".to_string(),
+            l: "99.50".to_string(),
+            c: "100.50".to_string(),
+            v: "3".to_string(),
+            v_2: "301.50".to_string(),
+            n: 2,
+        },
+    };
+    assert_wire(
+        &kline,
+        json!({
+            "type": "KlineUpdateMessage",
+            "data": {
+                "e": "kline",
+                "s": "TATA_INR",
+                "i": "1m",
+                "t": 1_700_000_040_000u64,
+                "T": 1_700_000_099_999u64,
+                "o": "100.00",
+                "h": "101.00",
+                "l": "99.50",
+                "c": "100.50",
+                "v": "3",
+                "v_2": "301.50",
+                "n": 2
+            }
+        }),
+    );
+}
+
+#[test]
+fn stream_names() {
+    assert!(is_valid_stream("depth@TATA_INR"));
+    assert!(is_valid_stream("kline@TATA_INR_1m"));
+    assert!(is_valid_stream("kline@TATA_INR_1d"));
+    assert!(!is_valid_stream("kline@TATA_INR"));
+    assert!(!is_valid_stream("kline@TATA_INR_2m"));
+    assert!(!is_valid_stream("kline@_1m"));
+    assert!(!is_valid_stream("candles@TATA_INR"));
+}
+
+#[test]
+fn db_trade_added() {
+    let msg = DbMessage::TradeAdded {
+        data: TradeAdded {
+            id: "9".to_string(),
+            is_buyer_maker: false,
+            price: decimal("100.05"),
+            quantity: decimal("2"),
+            quotequantity: decimal("200.1"),
+            timestamp: 0,
+            market: "TATA_INR".to_string(),
+        },
+    };
+    assert_wire(
+        &msg,
+        json!({
+            "type": "TradeAdded",
+            "data": {
+                "id": "9",
+                "is_buyer_maker": false,
+                "price": "100.05",
+                "quantity": "2",
+                "quotequantity": "200.1",
+                "timestamp": 0,
+                "market": "TATA_INR"
+            }
+        }),
+    );
+}
+
+#[test]
+fn db_entries_carry_their_id_beside_the_update() {
+    let entry = DbEntry {
+        id: "12:2".to_string(),
+        message: DbMessage::OrderUpdate {
+            data: OrderUpdate {
+                order_id: "42".to_string(),
+                executed_qty: decimal("1"),
+                market: None,
+                price: None,
+                quantity: None,
+                side: None,
+                cancelled_qty: None,
+            },
+        },
+    };
+    assert_wire(
+        &entry,
+        json!({
+            "id": "12:2",
+            "type": "OrderUpdate",
+            "data": {
+                "order_id": "42",
+                "executed_qty": "1",
+                "market": null,
+                "price": null,
+                "quantity": null,
+                "side": null,
+                "cancelled_qty": null
+            }
+        }),
+    );
+}
+
+#[test]
+fn ws_subscribe() {
+    let request = WsRequest {
+        method: WsMethod::SUBSCRIBE,
+        params: vec!["depth@TATA_INR".to_string()],
+        id: Some(1),
+    };
+    assert_wire(
+        &request,
+        json!({ "method": "SUBSCRIBE", "params": ["depth@TATA_INR"], "id": 1 }),
+    );
+
+    assert_wire(
+        &WsResponse {
+            id: Some(1),
+            error: None,
+        },
+        json!({ "id": 1 }),
+    );
+    assert_wire(
+        &WsResponse {
+            id: None,
+            error: Some("invalid stream: bogus".to_string()),
+        },
+        json!({ "id": null, "error": "invalid stream: bogus" }),
+    );
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+
+pub type ClientId = u64;
+
+// updates a client may have waiting before it counts as lagging and is disconnected
+pub const CLIENT_BUFFER: usize = 1024;
+
+#[derive(Default)]
+struct State {
+    clients: HashMap<ClientId, Sender<Arc<str>>>,
+    streams: HashMap<String, HashSet<ClientId>>, // only streams with at least one subscriber
+}
+
+impl State {
+    // true when a stream lost its last subscriber
+    fn remove(&mut self, id: ClientId) -> bool {
+        self.clients.remove(&id);
+        let before = self.streams.len();
+        self.streams.retain(|_, subscribers| {
+            subscribers.remove(&id);
+            !subscribers.is_empty()
+        });
+        self.streams.len() != before
+    }
+}
+
+/// Who is subscribed to what. Sessions register and (un)subscribe here, the Redis listener
+/// follows the set of wanted streams and hands every update back for fan-out.
+#[derive(Default)]
+pub struct Hub {
+    state: Mutex<State>,
+    next_id: AtomicU64,
+    changed: AtomicBool, // the set of wanted streams changed since the listener last looked
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a client and returns the receiving end of its updates, which closes if the client
+    /// falls `CLIENT_BUFFER` updates behind.
+    pub fn register(&self) -> (ClientId, Receiver<Arc<str>>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(CLIENT_BUFFER);
+        self.state.lock().unwrap().clients.insert(id, sender);
+        (id, receiver)
+    }
+
+    pub fn subscribe(&self, id: ClientId, stream: &str) {
+        let mut state = self.state.lock().unwrap();
+        let subscribers = state.streams.entry(stream.to_string()).or_default();
+        if subscribers.is_empty() {
+            self.changed.store(true, Ordering::Release);
+        }
+        subscribers.insert(id);
+    }
+
+    pub fn unsubscribe(&self, id: ClientId, stream: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(subscribers) = state.streams.get_mut(stream) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                state.streams.remove(stream);
+                self.changed.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Drops a disconnected client from every stream.
+    pub fn remove(&self, id: ClientId) {
+        if self.state.lock().unwrap().remove(id) {
+            self.changed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Streams that currently have subscribers.
+    pub fn streams(&self) -> HashSet<String> {
+        self.state.lock().unwrap().streams.keys().cloned().collect()
+    }
+
+    /// Whether the wanted streams changed since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::AcqRel)
+    }
+
+    /// Sends `frame` to every subscriber of `stream`. Subscribers that are too far behind
+    /// to take it are dropped, which closes their session.
+    pub fn publish(&self, stream: &str, frame: &str) {
+        let mut state = self.state.lock().unwrap();
+        let Some(subscribers) = state.streams.get(stream) else {
+            return;
+        };
+        let frame: Arc<str> = Arc::from(frame);
+        let mut lagging = Vec::new();
+        for id in subscribers {
+            if let Some(sender) = state.clients.get(id) {
+                // a closed receiver means the session is already on its way out
+                if let Err(TrySendError::Full(_)) = sender.try_send(frame.clone()) {
+                    lagging.push(*id);
+                }
+            }
+        }
+        for id in lagging {
+            eprintln!(
+                "Disconnecting websocket {}, {} updates behind",
+                id, CLIENT_BUFFER
+            );
+            if state.remove(id) {
+                self.changed.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(receiver: &mut Receiver<Arc<str>>) -> Vec<String> {
+        std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|frame| frame.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn updates_reach_only_the_streams_subscribers() {
+        let hub = Hub::new();
+        let (alice, mut alice_updates) = hub.register();
+        let (bob, mut bob_updates) = hub.register();
+        hub.subscribe(alice, "depth@TATA_INR");
+        hub.subscribe(bob, "depth@TATA_INR");
+        hub.subscribe(bob, "trade@TATA_INR");
+        assert!(hub.take_changed());
+
+        hub.publish("depth@TATA_INR", "d1");
+        hub.publish("trade@TATA_INR", "t1");
+        hub.publish("ticker@TATA_INR", "x1");
+        assert_eq!(received(&mut alice_updates), vec!["d1"]);
+        assert_eq!(received(&mut bob_updates), vec!["d1", "t1"]);
+
+        hub.unsubscribe(bob, "depth@TATA_INR");
+        assert!(!hub.take_changed());
+        hub.publish("depth@TATA_INR", "d2");
+        assert_eq!(received(&mut alice_updates), vec!["d2"]);
+        assert!(received(&mut bob_updates).is_empty());
+
+        hub.remove(alice);
+        assert!(hub.take_changed());
+        assert_eq!(hub.streams(), HashSet::from(["trade@TATA_INR".to_string()]));
+    }
+
+    #[test]
+    fn lagging_clients_are_disconnected() {
+        let hub = Hub::new();
+        let (slow, mut slow_updates) = hub.register();
+        let (fast, mut fast_updates) = hub.register();
+        hub.subscribe(slow, "trade@TATA_INR");
+        hub.subscribe(fast, "trade@TATA_INR");
+
+        for n in 0..CLIENT_BUFFER {
+            hub.publish("trade@TATA_INR", &n.to_string());
+            assert_eq!(received(&mut fast_updates).len(), 1);
+        }
+        hub.publish("trade@TATA_INR", "one too many");
+
+        assert_eq!(received(&mut fast_updates), vec!["one too many"]);
+        assert_eq!(received(&mut slow_updates).len(), CLIENT_BUFFER);
+        assert!(slow_updates.try_recv().is_err());
+        assert!(slow_updates.is_closed());
+        hub.publish("trade@TATA_INR", "after");
+        assert_eq!(received(&mut fast_updates), vec!["after"]);
+    }
+}
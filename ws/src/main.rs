@@ -0,0 +1,28 @@
+use actix_web::{web, App, HttpServer};
+use protocol::transport::RedisConfig;
+use std::env;
+
+mod hub;
+mod pubsub;
+mod session;
+
+use hub::Hub;
+
+const DEFAULT_WS_ADDR: &str = "127.0.0.1:8081";
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let addr = env::var("WS_ADDR").unwrap_or_else(|_| DEFAULT_WS_ADDR.to_string());
+    let hub = web::Data::new(Hub::new());
+    pubsub::spawn(&RedisConfig::from_env(), hub.clone().into_inner())
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(hub.clone())
+            .route("/", web::get().to(session::connect))
+    })
+    .bind(addr)?
+    .run()
+    .await
+}
@@ -0,0 +1,80 @@
+use crate::hub::Hub;
+use protocol::to_ws::WsMessage;
+use protocol::transport::RedisConfig;
+use redis::RedisResult;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how often the listener stops waiting for messages to pick up subscription changes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Starts the thread that keeps one Redis subscription per wanted stream and forwards what
+/// arrives to the hub. Fails only on an invalid URL.
+pub fn spawn(config: &RedisConfig, hub: Arc<Hub>) -> RedisResult<()> {
+    let client = redis::Client::open(config.url.as_str())?;
+    thread::spawn(move || {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let started = Instant::now();
+            if let Err(e) = listen(&client, &hub) {
+                eprintln!(
+                    "Redis subscription lost, reconnecting in {:?}: {}",
+                    backoff, e
+                );
+            }
+            // a connection that held up for a while starts over with a short wait
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = INITIAL_BACKOFF;
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+    Ok(())
+}
+
+// runs until the connection fails; every wanted stream is subscribed afresh on each call
+fn listen(client: &redis::Client, hub: &Hub) -> RedisResult<()> {
+    let mut conn = client.get_connection_with_timeout(CONNECT_TIMEOUT)?;
+    let mut pubsub = conn.as_pubsub();
+    pubsub.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut subscribed: HashSet<String> = HashSet::new();
+    let mut stale = true;
+
+    loop {
+        if stale || hub.take_changed() {
+            let wanted = hub.streams();
+            for stream in wanted.difference(&subscribed) {
+                pubsub.subscribe(stream)?;
+            }
+            for stream in subscribed.difference(&wanted) {
+                pubsub.unsubscribe(stream)?;
+            }
+            subscribed = wanted;
+            stale = false;
+        }
+
+        let msg = match pubsub.get_message() {
+            Ok(msg) => msg,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(e),
+        };
+        let stream = msg.get_channel_name();
+        let frame = msg
+            .get_payload::<String>()
+            .map_err(|e| e.to_string())
+            .and_then(|payload| {
+                serde_json::from_str::<WsMessage>(&payload).map_err(|e| e.to_string())
+            })
+            .and_then(|update| update.to_client_json(stream).map_err(|e| e.to_string()));
+        match frame {
+            Ok(frame) => hub.publish(stream, &frame),
+            Err(e) => eprintln!("Dropping unreadable update on {}: {}", stream, e),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::hub::{ClientId, Hub};
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws::{hash_key, CloseCode, CloseReason, Codec, Frame, Message};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream, StreamExt};
+use protocol::from_ws::{WsMethod, WsRequest, WsResponse};
+use protocol::to_ws::is_valid_stream;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+// encoded frames a client may leave unread before its connection is closed
+const FRAME_BUFFER: usize = 1024;
+
+/// Upgrades the request to a websocket and serves it until either side closes.
+pub async fn connect(
+    req: HttpRequest,
+    payload: web::Payload,
+    hub: web::Data<Hub>,
+) -> Result<HttpResponse, Error> {
+    actix_http::ws::verify_handshake(req.head())?;
+    // present and well-formed, or the handshake check above would have failed
+    let key = req.headers().get(header::SEC_WEBSOCKET_KEY).unwrap();
+    let accept = hash_key(key.as_bytes());
+
+    let (id, updates) = hub.register();
+    let (frames, outgoing) = mpsc::channel::<Bytes>(FRAME_BUFFER);
+    let session = Session {
+        id,
+        codec: Codec::new(),
+        frames,
+        hub: hub.into_inner(),
+    };
+    actix_web::rt::spawn(session.run(payload, updates));
+
+    let body = stream::unfold(outgoing, |mut outgoing| async move {
+        let frame = outgoing.recv().await?;
+        Some((Ok::<_, Error>(frame), outgoing))
+    });
+    Ok(HttpResponse::SwitchingProtocols()
+        .upgrade("websocket")
+        .insert_header((
+            header::SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_bytes(&accept).unwrap(),
+        ))
+        .streaming(body))
+}
+
+struct Session {
+    id: ClientId,
+    codec: Codec,
+    frames: Sender<Bytes>, // encoded frames on their way to the client
+    hub: Arc<Hub>,
+}
+
+impl Session {
+    async fn run(mut self, mut payload: web::Payload, mut updates: Receiver<Arc<str>>) {
+        let mut buf = BytesMut::new();
+        loop {
+            let open = tokio::select! {
+                chunk = payload.next() => match chunk {
+                    Some(Ok(chunk)) => {
+                        buf.extend_from_slice(&chunk);
+                        self.read(&mut buf)
+                    }
+                    _ => false,
+                },
+                update = updates.recv() => match update {
+                    Some(update) => self.send(Message::Text((&*update).into())),
+                    // the hub dropped this client for falling behind
+                    None => {
+                        self.send(Message::Close(Some(CloseReason {
+                            code: CloseCode::Again,
+                            description: Some("too far behind".to_string()),
+                        })));
+                        false
+                    }
+                },
+            };
+            if !open {
+                break;
+            }
+        }
+        self.hub.remove(self.id);
+    }
+
+    // handles every complete frame in `buf`; false once the connection should close
+    fn read(&mut self, buf: &mut BytesMut) -> bool {
+        loop {
+            let open = match self.codec.decode(buf) {
+                Ok(Some(Frame::Text(text))) => self.request(&text),
+                Ok(Some(Frame::Ping(msg))) => self.send(Message::Pong(msg)),
+                Ok(Some(Frame::Pong(_))) => true,
+                Ok(Some(Frame::Close(reason))) => {
+                    self.send(Message::Close(reason));
+                    false
+                }
+                Ok(Some(Frame::Binary(_) | Frame::Continuation(_))) => {
+                    self.respond(None, Some("only text frames are supported".to_string()))
+                }
+                Ok(None) => return true,
+                Err(e) => {
+                    eprintln!("Closing websocket {}: {}", self.id, e);
+                    false
+                }
+            };
+            if !open {
+                return false;
+            }
+        }
+    }
+
+    fn request(&mut self, text: &[u8]) -> bool {
+        let request = match serde_json::from_slice::<WsRequest>(text) {
+            Ok(request) => request,
+            Err(e) => return self.respond(None, Some(format!("invalid request: {}", e))),
+        };
+        if let Some(stream) = request.params.iter().find(|s| !is_valid_stream(s)) {
+            return self.respond(request.id, Some(format!("invalid stream: {}", stream)));
+        }
+
+        for stream in &request.params {
+            match request.method {
+                WsMethod::SUBSCRIBE => self.hub.subscribe(self.id, stream),
+                WsMethod::UNSUBSCRIBE => self.hub.unsubscribe(self.id, stream),
+            }
+        }
+        self.respond(request.id, None)
+    }
+
+    fn respond(&mut self, id: Option<u64>, error: Option<String>) -> bool {
+        let response = serde_json::to_string(&WsResponse { id, error }).unwrap();
+        self.send(Message::Text(response.into()))
+    }
+
+    // false when the client is gone or not reading what it is sent
+    fn send(&mut self, msg: Message) -> bool {
+        let mut frame = BytesMut::new();
+        if let Err(e) = self.codec.encode(msg, &mut frame) {
+            eprintln!("Failed to encode websocket frame: {}", e);
+            return false;
+        }
+        self.frames.try_send(frame.freeze()).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(hub: &Arc<Hub>) -> (Session, mpsc::Receiver<Bytes>) {
+        let (id, _) = hub.register();
+        let (frames, outgoing) = mpsc::channel(FRAME_BUFFER);
+        let session = Session {
+            id,
+            codec: Codec::new(),
+            frames,
+            hub: hub.clone(),
+        };
+        (session, outgoing)
+    }
+
+    // the text of the next frame sent to the client
+    fn reply(outgoing: &mut mpsc::Receiver<Bytes>) -> String {
+        let mut buf = BytesMut::from(&outgoing.try_recv().unwrap()[..]);
+        match Codec::new().client_mode().decode(&mut buf) {
+            Ok(Some(Frame::Text(text))) => String::from_utf8(text.to_vec()).unwrap(),
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn requests_naming_an_invalid_stream_change_nothing() {
+        let hub = Arc::new(Hub::new());
+        let (mut session, mut outgoing) = session(&hub);
+
+        let request =
+            r#"{"method":"SUBSCRIBE","params":["trade@TATA_INR","candles@TATA_INR"],"id":1}"#;
+        assert!(session.request(request.as_bytes()));
+        assert_eq!(
+            reply(&mut outgoing),
+            r#"{"id":1,"error":"invalid stream: candles@TATA_INR"}"#
+        );
+        assert!(hub.streams().is_empty());
+    }
+
+    #[test]
+    fn subscriptions_follow_subscribe_and_unsubscribe() {
+        let hub = Arc::new(Hub::new());
+        let (mut session, mut outgoing) = session(&hub);
+
+        let subscribe =
+            r#"{"method":"SUBSCRIBE","params":["trade@TATA_INR","kline@TATA_INR_1m"],"id":1}"#;
+        assert!(session.request(subscribe.as_bytes()));
+        assert_eq!(reply(&mut outgoing), r#"{"id":1}"#);
+        assert_eq!(hub.streams().len(), 2);
+
+        let unsubscribe = r#"{"method":"UNSUBSCRIBE","params":["trade@TATA_INR"],"id":2}"#;
+        assert!(session.request(unsubscribe.as_bytes()));
+        assert_eq!(reply(&mut outgoing), r#"{"id":2}"#);
+        assert_eq!(
+            hub.streams(),
+            std::collections::HashSet::from(["kline@TATA_INR_1m".to_string()])
+        );
+    }
+
+    #[test]
+    fn a_client_that_stops_reading_is_let_go() {
+        let hub = Arc::new(Hub::new());
+        let (mut session, _outgoing) = session(&hub);
+
+        for _ in 0..FRAME_BUFFER {
+            assert!(session.send(Message::Text("update".into())));
+        }
+        assert!(!session.send(Message::Text("update".into())));
+    }
+}